@@ -0,0 +1,289 @@
+use crate::theme::ThemeType;
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use serde_json::Value;
+
+#[derive(Debug, Parser, Clone)]
+pub struct AmbientArgs {
+    /// Below this illuminance in lux the theme is at most `dark-soft`
+    #[arg(long, default_value = "50", env = "LUX_DIM_BELOW")]
+    pub lux_dim_below: f64,
+
+    /// Above this illuminance in lux the theme is at least `light-soft`
+    #[arg(long, default_value = "1000", env = "LUX_BRIGHT_ABOVE")]
+    pub lux_bright_above: f64,
+
+    /// How far in percent the illuminance must move back past a threshold to undo its effect
+    #[arg(long, default_value = "20", env = "LUX_HYSTERESIS_PERCENT")]
+    pub lux_hysteresis_percent: f64,
+}
+
+/// How the room's measured light compares to the thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ambient {
+    Dim,
+    Normal,
+    Bright,
+}
+
+/// Tracks the ambient light level reported by a lux sensor.
+#[derive(Debug, Clone)]
+pub struct AmbientLight {
+    dim_below: f64,
+    bright_above: f64,
+    hysteresis: f64,
+    state: Ambient,
+}
+
+impl AmbientLight {
+    pub fn from_args(args: &AmbientArgs) -> Result<Self> {
+        if args.lux_dim_below >= args.lux_bright_above {
+            bail!(
+                "Dim threshold ({} lux) must be below the bright threshold ({} lux)",
+                args.lux_dim_below,
+                args.lux_bright_above
+            );
+        }
+        if !(0.0..100.0).contains(&args.lux_hysteresis_percent) {
+            bail!("Lux hysteresis must be between 0 and 100 percent");
+        }
+
+        Ok(Self {
+            dim_below: args.lux_dim_below,
+            bright_above: args.lux_bright_above,
+            hysteresis: args.lux_hysteresis_percent / 100.0,
+            state: Ambient::Normal,
+        })
+    }
+
+    pub fn state(&self) -> Ambient {
+        self.state
+    }
+
+    /// Record a new reading; returns whether the ambient state changed.
+    pub fn update(&mut self, lux: f64) -> bool {
+        let dim_exit = self.dim_below * (1.0 + self.hysteresis);
+        let bright_exit = self.bright_above * (1.0 - self.hysteresis);
+
+        let next = match self.state {
+            _ if lux < self.dim_below => Ambient::Dim,
+            _ if lux > self.bright_above => Ambient::Bright,
+            Ambient::Dim if lux < dim_exit => Ambient::Dim,
+            Ambient::Bright if lux > bright_exit => Ambient::Bright,
+            _ => Ambient::Normal,
+        };
+
+        let changed = next != self.state;
+        self.state = next;
+        changed
+    }
+
+    /// Push a solar theme darker in a dim room or lighter in a bright one.
+    ///
+    /// The adjusted theme keeps its solar phase and only publishes a different theme string.
+    /// Only the standard phases and the optional phases have a known brightness; elevation
+    /// bands and custom themes are left alone.
+    pub fn adjust(&self, theme: ThemeType) -> ThemeType {
+        let Some(level) = brightness_level(&theme) else {
+            return theme;
+        };
+
+        let adjusted = match self.state {
+            Ambient::Dim if level > DIM_MAX_LEVEL => DIM_THEME,
+            Ambient::Bright if level < BRIGHT_MIN_LEVEL => BRIGHT_THEME,
+            _ => return theme,
+        };
+        ThemeType::Ambient {
+            phase: Box::new(theme),
+            theme: adjusted.to_string(),
+        }
+    }
+}
+
+/// Brightest level allowed in a dim room, that of `dark-soft`.
+const DIM_MAX_LEVEL: u8 = 2;
+
+/// Theme published in a dim room instead of a lighter one.
+const DIM_THEME: &str = "dark-soft";
+
+/// Darkest level allowed in a bright room, that of `light-soft`.
+const BRIGHT_MIN_LEVEL: u8 = 3;
+
+/// Theme published in a bright room instead of a darker one.
+const BRIGHT_THEME: &str = "light-soft";
+
+/// Rank of a theme from 0 (`dark`) to 4 (`light`).
+fn brightness_level(theme: &ThemeType) -> Option<u8> {
+    match theme {
+        ThemeType::Night => Some(0),
+        ThemeType::AstronomicalDawn | ThemeType::AstronomicalDusk => Some(1),
+        ThemeType::NauticalDawn | ThemeType::NauticalDusk | ThemeType::BlueHour => Some(2),
+        ThemeType::CivilDawn | ThemeType::CivilDusk => Some(3),
+        ThemeType::Sunrise | ThemeType::Day | ThemeType::GoldenHour | ThemeType::SolarNoon => {
+            Some(4)
+        }
+        ThemeType::Band { .. } | ThemeType::Ambient { .. } | ThemeType::Custom(_) => None,
+    }
+}
+
+/// Parse a lux reading sent as a bare number or as a JSON object from Zigbee2MQTT or
+/// Home Assistant.
+pub fn parse_lux_payload(payload: &str) -> Result<f64> {
+    if let Ok(lux) = payload.trim().parse::<f64>() {
+        return Ok(lux);
+    }
+
+    let value: Value = serde_json::from_str(payload).context("Invalid lux payload")?;
+    ["illuminance_lux", "illuminance", "lux", "state"]
+        .iter()
+        .find_map(|key| match value.get(key)? {
+            Value::Number(number) => number.as_f64(),
+            Value::String(text) => text.parse().ok(),
+            _ => None,
+        })
+        .context("No illuminance in lux payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ambient_args() -> AmbientArgs {
+        AmbientArgs {
+            lux_dim_below: 50.0,
+            lux_bright_above: 1000.0,
+            lux_hysteresis_percent: 20.0,
+        }
+    }
+
+    /// Dim below 50 lux until back above 60, bright above 1000 lux until back below 800.
+    fn ambient() -> AmbientLight {
+        AmbientLight::from_args(&ambient_args()).unwrap()
+    }
+
+    #[test]
+    fn update_applies_hysteresis() {
+        let mut ambient = ambient();
+        let readings = [
+            (300.0, false, Ambient::Normal),
+            (49.0, true, Ambient::Dim),
+            (55.0, false, Ambient::Dim),
+            (60.0, true, Ambient::Normal),
+            (55.0, false, Ambient::Normal),
+            (1001.0, true, Ambient::Bright),
+            (900.0, false, Ambient::Bright),
+            (800.0, true, Ambient::Normal),
+            (10.0, true, Ambient::Dim),
+            (2000.0, true, Ambient::Bright),
+        ];
+        for (lux, changed, state) in readings {
+            assert_eq!(ambient.update(lux), changed, "{} lux", lux);
+            assert_eq!(ambient.state(), state, "{} lux", lux);
+        }
+    }
+
+    #[test]
+    fn from_args_rejects_bad_thresholds() {
+        let args = AmbientArgs {
+            lux_dim_below: 1000.0,
+            lux_bright_above: 50.0,
+            ..ambient_args()
+        };
+        assert!(AmbientLight::from_args(&args).is_err());
+
+        let args = AmbientArgs {
+            lux_hysteresis_percent: 100.0,
+            ..ambient_args()
+        };
+        assert!(AmbientLight::from_args(&args).is_err());
+    }
+
+    fn adjusted(phase: ThemeType, theme: &str) -> ThemeType {
+        ThemeType::Ambient {
+            phase: Box::new(phase),
+            theme: theme.to_string(),
+        }
+    }
+
+    #[test]
+    fn adjust_clamps_standard_phases() {
+        let mut ambient = ambient();
+        assert_eq!(ambient.adjust(ThemeType::Day), ThemeType::Day);
+
+        ambient.update(10.0);
+        assert_eq!(
+            ambient.adjust(ThemeType::Day),
+            adjusted(ThemeType::Day, "dark-soft")
+        );
+        assert_eq!(
+            ambient.adjust(ThemeType::CivilDawn),
+            adjusted(ThemeType::CivilDawn, "dark-soft")
+        );
+        assert_eq!(ambient.adjust(ThemeType::BlueHour), ThemeType::BlueHour);
+        assert_eq!(ambient.adjust(ThemeType::Night), ThemeType::Night);
+
+        ambient.update(5000.0);
+        assert_eq!(
+            ambient.adjust(ThemeType::Night),
+            adjusted(ThemeType::Night, "light-soft")
+        );
+        assert_eq!(
+            ambient.adjust(ThemeType::NauticalDusk),
+            adjusted(ThemeType::NauticalDusk, "light-soft")
+        );
+        assert_eq!(ambient.adjust(ThemeType::CivilDusk), ThemeType::CivilDusk);
+        assert_eq!(ambient.adjust(ThemeType::SolarNoon), ThemeType::SolarNoon);
+    }
+
+    #[test]
+    fn adjusted_theme_keeps_its_solar_phase() {
+        let mut ambient = ambient();
+        ambient.update(10.0);
+
+        let theme = ambient.adjust(ThemeType::Day);
+        assert_eq!(theme.to_theme_string(), "dark-soft");
+        assert_eq!(theme.phase_name(), "Day");
+        assert_eq!(theme.to_description(), ThemeType::Day.to_description());
+    }
+
+    #[test]
+    fn adjust_leaves_bands_and_custom_themes() {
+        let mut ambient = ambient();
+        ambient.update(10.0);
+
+        let band = ThemeType::Band {
+            name: "golden".to_string(),
+            theme: "light-warm".to_string(),
+        };
+        assert_eq!(ambient.adjust(band.clone()), band);
+        let custom = ThemeType::Custom("solarized".to_string());
+        assert_eq!(ambient.adjust(custom.clone()), custom);
+    }
+
+    #[test]
+    fn parses_lux_payloads() {
+        let cases = [
+            ("42", 42.0),
+            (" 12.5\n", 12.5),
+            (r#"{"illuminance_lux": 310, "illuminance": 24000}"#, 310.0),
+            (r#"{"illuminance": 180}"#, 180.0),
+            (r#"{"lux": 7.5}"#, 7.5),
+            (r#"{"state": "523.4", "unit_of_measurement": "lx"}"#, 523.4),
+        ];
+        for (payload, lux) in cases {
+            assert_eq!(parse_lux_payload(payload).unwrap(), lux, "{}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_payloads_without_lux() {
+        for payload in [
+            "",
+            "bright",
+            r#"{"state": "unavailable"}"#,
+            r#"{"battery": 90}"#,
+        ] {
+            assert!(parse_lux_payload(payload).is_err(), "{}", payload);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use clap::Parser;
+use std::time::{Duration as StdDuration, Instant};
+
+/// Shortest real wait for a simulated duration, so huge speeds never produce a
+/// zero-length timer period.
+const MIN_REAL_DURATION: StdDuration = StdDuration::from_millis(1);
+
+/// Longest real wait for a simulated duration; anything further off is effectively never,
+/// and this keeps `Instant` arithmetic from overflowing.
+const MAX_REAL_DURATION: StdDuration = StdDuration::from_secs(365 * 24 * 60 * 60);
+
+#[derive(Debug, Parser, Clone)]
+pub struct ClockArgs {
+    /// Run on a virtual clock starting at this RFC 3339 time instead of the system clock
+    #[arg(long, env = "SIMULATE_FROM")]
+    pub simulate_from: Option<DateTime<Utc>>,
+
+    /// How many times faster than real time the simulated clock runs
+    #[arg(
+        long,
+        default_value = "1",
+        env = "SIMULATE_SPEED",
+        requires = "simulate_from"
+    )]
+    pub speed: f64,
+}
+
+/// Source of the current time for everything that depends on the sun.
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    System,
+    /// A virtual clock that started at `start` when the process reached `started_at`
+    /// and advances `speed` times faster than real time.
+    Simulated {
+        start: DateTime<Utc>,
+        started_at: Instant,
+        speed: f64,
+    },
+}
+
+impl Clock {
+    pub fn from_args(args: &ClockArgs) -> Result<Self> {
+        let Some(start) = args.simulate_from else {
+            return Ok(Clock::System);
+        };
+
+        if !(args.speed.is_finite() && args.speed > 0.0) {
+            bail!("Simulation speed must be a positive number");
+        }
+
+        Ok(Clock::Simulated {
+            start,
+            started_at: Instant::now(),
+            speed: args.speed,
+        })
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Simulated {
+                start,
+                started_at,
+                speed,
+            } => {
+                let elapsed = started_at.elapsed().as_secs_f64() * speed;
+                *start + Duration::milliseconds((elapsed * 1000.0) as i64)
+            }
+        }
+    }
+
+    /// Real time to wait until `duration` has passed on this clock.
+    ///
+    /// Simulated waits are clamped between a millisecond and a year, so extreme speeds
+    /// neither stop the timers nor overflow them.
+    pub fn real_duration(&self, duration: Duration) -> StdDuration {
+        let duration = duration.to_std().unwrap_or_default();
+        match self {
+            Clock::System => duration,
+            Clock::Simulated { speed, .. } => {
+                StdDuration::try_from_secs_f64(duration.as_secs_f64() / speed)
+                    .unwrap_or(MAX_REAL_DURATION)
+                    .clamp(MIN_REAL_DURATION, MAX_REAL_DURATION)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulated(speed: f64) -> Clock {
+        Clock::from_args(&ClockArgs {
+            simulate_from: Some(Utc::now()),
+            speed,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn real_duration_scales_by_speed() {
+        assert_eq!(
+            simulated(60.0).real_duration(Duration::minutes(1)),
+            StdDuration::from_secs(1)
+        );
+        assert_eq!(
+            Clock::System.real_duration(Duration::seconds(5)),
+            StdDuration::from_secs(5)
+        );
+        assert_eq!(
+            Clock::System.real_duration(Duration::seconds(-5)),
+            StdDuration::ZERO
+        );
+    }
+
+    #[test]
+    fn real_duration_is_at_least_a_millisecond() {
+        let clock = simulated(1e12);
+        assert_eq!(clock.real_duration(Duration::hours(1)), MIN_REAL_DURATION);
+        assert_eq!(clock.real_duration(Duration::zero()), MIN_REAL_DURATION);
+    }
+
+    #[test]
+    fn real_duration_saturates_at_tiny_speeds() {
+        let clock = simulated(1e-300);
+        assert_eq!(clock.real_duration(Duration::days(365)), MAX_REAL_DURATION);
+        assert_eq!(
+            simulated(1e-3).real_duration(Duration::days(1)),
+            MAX_REAL_DURATION
+        );
+    }
+
+    #[test]
+    fn speed_must_be_positive_and_finite() {
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let args = ClockArgs {
+                simulate_from: Some(Utc::now()),
+                speed,
+            };
+            assert!(Clock::from_args(&args).is_err(), "speed {}", speed);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::theme::{ElevationBand, ExtraPhase, ThemeType};
+use anyhow::{Context, Result, bail};
+use chrono::Duration;
+use clap::Parser;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Debug, Parser, Clone)]
+pub struct ConfigArgs {
+    /// JSON file with theme settings, e.g. `{"offsets": {"sunrise": "+15m"}}`
+    #[arg(long, env = "THEME_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Shift the start of a phase from its solar event, e.g. `sunrise=+15m` or `civil-dusk=-10m`
+    #[arg(
+        long = "offset",
+        value_name = "PHASE=OFFSET",
+        env = "THEME_OFFSETS",
+        value_delimiter = ',',
+        value_parser = parse_phase_offset
+    )]
+    pub offsets: Vec<(ThemeType, Duration)>,
+
+    /// Optional phases to enable, e.g. `golden-hour,blue-hour,solar-noon`
+    #[arg(long, env = "EXTRA_PHASES", value_enum, value_delimiter = ',')]
+    pub extra_phases: Vec<ExtraPhase>,
+}
+
+/// Theme settings merged from the config file and the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub offsets: HashMap<ThemeType, Duration>,
+    pub bands: Vec<ElevationBand>,
+    pub extra_phases: Vec<ExtraPhase>,
+}
+
+/// On-disk layout of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    offsets: BTreeMap<String, String>,
+    bands: Vec<ElevationBand>,
+    extra_phases: Vec<ExtraPhase>,
+}
+
+impl Config {
+    /// Load the config file, if any, with command line offsets taking precedence.
+    pub fn from_args(args: &ConfigArgs) -> Result<Self> {
+        let mut config = Config::default();
+
+        if let Some(path) = &args.config {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file {}", path.display()))?;
+            let file: ConfigFile = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+            for (phase, offset) in file.offsets {
+                let (theme, offset) = parse_phase_offset(&format!("{}={}", phase, offset))?;
+                config.offsets.insert(theme, offset);
+            }
+
+            for band in &file.bands {
+                if band.name.trim().is_empty() {
+                    bail!("Elevation band without a name in {}", path.display());
+                }
+                if band.min >= band.max {
+                    bail!(
+                        "Elevation band {} must have min below max, got {}° to {}°",
+                        band.name,
+                        band.min,
+                        band.max
+                    );
+                }
+                info!(
+                    "🌗 Elevation band {}: {}° to {}°{}",
+                    band.name,
+                    band.min,
+                    band.max,
+                    band.direction
+                        .map(|direction| format!(" while {:?}", direction).to_lowercase())
+                        .unwrap_or_default()
+                );
+            }
+            config.bands = file.bands;
+            config.extra_phases = file.extra_phases;
+            info!("Loaded config from {}", path.display());
+        }
+
+        config.offsets.extend(args.offsets.iter().cloned());
+        if !args.extra_phases.is_empty() {
+            config.extra_phases = args.extra_phases.clone();
+        }
+        if !config.extra_phases.is_empty() {
+            info!("✨ Extra phases: {:?}", config.extra_phases);
+        }
+        for (theme, offset) in &config.offsets {
+            info!("⏱️  {:?} offset: {}m", theme, offset.num_minutes());
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parse `phase=offset`, e.g. `sunrise=+15m`.
+fn parse_phase_offset(value: &str) -> Result<(ThemeType, Duration)> {
+    let Some((phase, offset)) = value.split_once('=') else {
+        bail!("Expected PHASE=OFFSET, got {}", value);
+    };
+    let theme = ThemeType::from_phase_name(phase)
+        .with_context(|| format!("Unknown phase: {}", phase.trim()))?;
+    Ok((theme, parse_offset(offset)?))
+}
+
+/// Largest shift allowed in either direction; beyond it a phase would move into another day.
+const MAX_OFFSET_HOURS: i64 = 12;
+
+/// Parse a signed duration made of `h`, `m` and `s` parts, e.g. `+15m`, `-1h30m` or `90s`.
+fn parse_offset(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    if rest.is_empty() {
+        bail!("Empty offset");
+    }
+
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let amount: i64 = digits
+            .parse()
+            .with_context(|| format!("Invalid offset: {}", value))?;
+        let part = match c {
+            'h' => Duration::try_hours(amount),
+            'm' => Duration::try_minutes(amount),
+            's' => Duration::try_seconds(amount),
+            _ => bail!(
+                "Invalid offset unit '{}' in {}, expected h, m or s",
+                c,
+                value
+            ),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .with_context(|| format!("Offset {} is out of range", value))?;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        bail!("Missing unit in offset {}, expected h, m or s", value);
+    }
+    if total > Duration::hours(MAX_OFFSET_HOURS) {
+        bail!(
+            "Offset {} is out of range, expected at most {}h either way",
+            value,
+            MAX_OFFSET_HOURS
+        );
+    }
+
+    Ok(total * sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signed_offsets() {
+        let cases = [
+            ("-1h30m", Duration::minutes(-90)),
+            ("+15m", Duration::minutes(15)),
+            ("15m", Duration::minutes(15)),
+            ("90s", Duration::seconds(90)),
+            (" 1h1m1s ", Duration::seconds(3661)),
+            ("+12h", Duration::hours(12)),
+            ("-720m", Duration::hours(-12)),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_offset(value).unwrap(), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_offsets() {
+        let cases = [
+            ("", "Empty offset"),
+            ("-", "Empty offset"),
+            ("15", "Missing unit"),
+            ("1h30", "Missing unit"),
+            ("15x", "Invalid offset unit"),
+            ("m", "Invalid offset"),
+            ("99999999999999h", "out of range"),
+            ("2000000000h", "out of range"),
+            ("-13h", "out of range"),
+            ("12h1s", "out of range"),
+        ];
+        for (value, message) in cases {
+            let error = parse_offset(value).unwrap_err().to_string();
+            assert!(error.contains(message), "{:?}: {}", value, error);
+        }
+    }
+
+    #[test]
+    fn parses_phase_offsets() {
+        assert_eq!(
+            parse_phase_offset("sunrise=+15m").unwrap(),
+            (ThemeType::Sunrise, Duration::minutes(15))
+        );
+        assert_eq!(
+            parse_phase_offset("civil_dusk=-1h30m").unwrap(),
+            (ThemeType::CivilDusk, Duration::minutes(-90))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_phase_offsets() {
+        let cases = [
+            ("sunrise", "Expected PHASE=OFFSET"),
+            ("noon=+15m", "Unknown phase: noon"),
+            ("sunrise=", "Empty offset"),
+            ("sunrise=15", "Missing unit"),
+        ];
+        for (value, message) in cases {
+            let error = parse_phase_offset(value).unwrap_err().to_string();
+            assert!(error.contains(message), "{:?}: {}", value, error);
+        }
+    }
+}
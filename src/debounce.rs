@@ -0,0 +1,186 @@
+use crate::theme::ThemeType;
+use chrono::{DateTime, Duration, Utc};
+
+/// What to do with the theme the main loop currently wants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// The wanted theme is already published.
+    Steady,
+    /// Publish this theme now.
+    Publish(ThemeType),
+    /// Keep the published theme; the wanted one may be published at this time if it holds.
+    Hold(DateTime<Utc>),
+}
+
+/// Holds back theme changes until the new theme has been wanted for `settle` and the
+/// published one has been shown for at least `min_dwell`.
+#[derive(Debug, Clone)]
+pub struct ThemeDebouncer {
+    settle: Duration,
+    min_dwell: Duration,
+    published: ThemeType,
+    published_at: DateTime<Utc>,
+    candidate: Option<(ThemeType, DateTime<Utc>)>,
+}
+
+impl ThemeDebouncer {
+    /// Start from `initial`, published at `now`.
+    pub fn new(
+        initial: ThemeType,
+        now: DateTime<Utc>,
+        settle: Duration,
+        min_dwell: Duration,
+    ) -> Self {
+        Self {
+            settle,
+            min_dwell,
+            published: initial,
+            published_at: now,
+            candidate: None,
+        }
+    }
+
+    /// The theme most recently published.
+    pub fn published(&self) -> &ThemeType {
+        &self.published
+    }
+
+    /// When the published theme was first published.
+    pub fn published_at(&self) -> DateTime<Utc> {
+        self.published_at
+    }
+
+    /// Decide whether `wanted` may replace the published theme at `now`.
+    pub fn update(&mut self, wanted: &ThemeType, now: DateTime<Utc>) -> Decision {
+        if *wanted == self.published {
+            self.candidate = None;
+            return Decision::Steady;
+        }
+
+        // A different wanted theme restarts the settle period
+        let since = match &self.candidate {
+            Some((candidate, since)) if candidate == wanted => *since,
+            _ => {
+                self.candidate = Some((wanted.clone(), now));
+                now
+            }
+        };
+
+        let ready_at = (since + self.settle).max(self.published_at + self.min_dwell);
+        if now < ready_at {
+            return Decision::Hold(ready_at);
+        }
+
+        self.published = wanted.clone();
+        self.published_at = now;
+        self.candidate = None;
+        Decision::Publish(wanted.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + Duration::seconds(seconds)
+    }
+
+    /// Published Night at 0s, with a 10s settle period and a 60s minimum dwell.
+    fn debouncer() -> ThemeDebouncer {
+        ThemeDebouncer::new(
+            ThemeType::Night,
+            at(0),
+            Duration::seconds(10),
+            Duration::seconds(60),
+        )
+    }
+
+    #[test]
+    fn published_theme_is_steady() {
+        let mut debouncer = debouncer();
+        assert_eq!(debouncer.update(&ThemeType::Night, at(5)), Decision::Steady);
+        assert_eq!(debouncer.published_at(), at(0));
+    }
+
+    #[test]
+    fn change_publishes_once_settled_and_dwelled() {
+        let mut debouncer = debouncer();
+        let day = ThemeType::Day;
+
+        assert_eq!(debouncer.update(&day, at(100)), Decision::Hold(at(110)));
+        assert_eq!(debouncer.update(&day, at(105)), Decision::Hold(at(110)));
+        assert_eq!(
+            debouncer.update(&day, at(110)),
+            Decision::Publish(day.clone())
+        );
+        assert_eq!(debouncer.published(), &day);
+        assert_eq!(debouncer.published_at(), at(110));
+        assert_eq!(debouncer.update(&day, at(111)), Decision::Steady);
+    }
+
+    #[test]
+    fn different_candidate_restarts_settle_period() {
+        let mut debouncer = debouncer();
+
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(100)),
+            Decision::Hold(at(110))
+        );
+        assert_eq!(
+            debouncer.update(&ThemeType::CivilDusk, at(105)),
+            Decision::Hold(at(115))
+        );
+        assert_eq!(
+            debouncer.update(&ThemeType::CivilDusk, at(115)),
+            Decision::Publish(ThemeType::CivilDusk)
+        );
+    }
+
+    #[test]
+    fn minimum_dwell_holds_a_settled_change() {
+        let mut debouncer = debouncer();
+
+        // Settled at 30s, but Night has to stay up until 60s
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(20)),
+            Decision::Hold(at(60))
+        );
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(45)),
+            Decision::Hold(at(60))
+        );
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(60)),
+            Decision::Publish(ThemeType::Day)
+        );
+
+        // The dwell restarts from the new publish
+        assert_eq!(
+            debouncer.update(&ThemeType::Night, at(80)),
+            Decision::Hold(at(120))
+        );
+    }
+
+    #[test]
+    fn returning_to_published_theme_drops_candidate() {
+        let mut debouncer = debouncer();
+
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(100)),
+            Decision::Hold(at(110))
+        );
+        assert_eq!(
+            debouncer.update(&ThemeType::Night, at(105)),
+            Decision::Steady
+        );
+
+        // Wanting Day again starts a fresh settle period rather than resuming the old one
+        assert_eq!(
+            debouncer.update(&ThemeType::Day, at(108)),
+            Decision::Hold(at(118))
+        );
+        assert_eq!(debouncer.published(), &ThemeType::Night);
+        assert_eq!(debouncer.published_at(), at(0));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::ambient::{AmbientArgs, AmbientLight};
+use crate::location::Location;
+use crate::theme::{ThemeEngine, ThemeType};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::Parser;
+use std::io::Write;
+
+#[derive(Debug, Parser, Clone)]
+pub struct ExplainArgs {
+    /// Instant to explain as an RFC 3339 time (default: now)
+    #[arg(long)]
+    pub at: Option<DateTime<Utc>>,
+
+    /// Explain the result as if this custom theme override were active
+    #[arg(long = "override", value_name = "THEME")]
+    pub theme_override: Option<String>,
+
+    /// Explain the result as if the ambient light sensor read this many lux
+    #[arg(long, value_name = "LUX")]
+    pub lux: Option<f64>,
+}
+
+/// The daemon settings that decide how the solar theme becomes the published one.
+pub struct Publishing<'a> {
+    pub ambient: &'a AmbientArgs,
+    /// Topic with lux readings, when an ambient light sensor is configured
+    pub lux_topic: Option<&'a str>,
+    pub settle: Duration,
+    pub min_dwell: Duration,
+}
+
+/// Print how the theme at `instant` is derived from the sun's position to stdout.
+pub fn print(
+    engine: &ThemeEngine,
+    location: &Location,
+    instant: DateTime<Utc>,
+    publishing: &Publishing,
+    args: &ExplainArgs,
+) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    let today = engine.local_date(instant);
+    let state = engine.theme_at(instant);
+
+    writeln!(
+        out,
+        "Instant:        {} ({} local)",
+        instant.format("%Y-%m-%d %H:%M:%S UTC"),
+        instant
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+    )?;
+    writeln!(
+        out,
+        "Location:       lat={:.4}, lon={:.4}, elevation={:.0}m",
+        location.latitude, location.longitude, location.elevation
+    )?;
+    writeln!(out, "Solar date:     {}", today)?;
+    writeln!(
+        out,
+        "Sun elevation:  {:.2}° ({})",
+        engine.sun_elevation(instant),
+        if engine.sun_rising(instant) {
+            "rising"
+        } else {
+            "setting"
+        }
+    )?;
+
+    writeln!(out)?;
+    writeln!(out, "Solar events on {} (UTC):", today)?;
+    let transitions = engine.transitions(today);
+    if transitions.is_empty() {
+        writeln!(out, "  none, the sun stays above or below every threshold")?;
+    }
+    for transition in &transitions {
+        let marker = if state.since == Some(transition.time) {
+            "->"
+        } else {
+            "  "
+        };
+        // Report the sun at the event itself, before any configured offset
+        let offset = engine.offset(&transition.theme);
+        let shift = if offset.is_zero() {
+            String::new()
+        } else {
+            format!(" ({:+}m from event)", offset.num_minutes())
+        };
+        writeln!(
+            out,
+            "{} {}  {:<17} sun at {:>6.2}°{}",
+            marker,
+            transition.time.format("%H:%M:%S"),
+            transition.theme.phase_name(),
+            engine.sun_elevation(transition.time - offset),
+            shift
+        )?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Reasoning:")?;
+    match state.since {
+        Some(since) => writeln!(
+            out,
+            "  1. Last transition was {} at {} UTC, {} ago",
+            state.theme.phase_name(),
+            since.format("%Y-%m-%d %H:%M:%S"),
+            format_duration(instant - since)
+        )?,
+        None => writeln!(
+            out,
+            "  1. No transition within a day (polar day or night), so the theme follows the \
+             sun's elevation: {}",
+            state.theme.phase_name()
+        )?,
+    }
+    if let Some(band) = engine.matching_band(instant) {
+        writeln!(
+            out,
+            "     The sun is within elevation band {} ({}° to {}°), which takes precedence over \
+             the standard phases",
+            band.name, band.min, band.max
+        )?;
+    }
+    match &state.next {
+        Some(next) => writeln!(
+            out,
+            "  2. Next transition is {} at {} UTC, in {}",
+            next.theme.phase_name(),
+            next.time.format("%Y-%m-%d %H:%M:%S"),
+            format_duration(next.time - instant)
+        )?,
+        None => writeln!(out, "  2. No upcoming transition within a day")?,
+    }
+
+    // Reasons the published theme may differ from the result
+    let mut caveats = Vec::new();
+
+    let solar_theme = state.theme.clone();
+    let adjusted = match (args.lux, publishing.lux_topic) {
+        (Some(lux), _) => {
+            let mut ambient = AmbientLight::from_args(publishing.ambient)?;
+            ambient.update(lux);
+            let adjusted = ambient.adjust(solar_theme.clone());
+            if adjusted == solar_theme {
+                writeln!(
+                    out,
+                    "  3. The room counts as {:?} at {} lux, which leaves the solar theme alone",
+                    ambient.state(),
+                    lux
+                )?;
+            } else {
+                writeln!(
+                    out,
+                    "  3. The room counts as {:?} at {} lux, so \"{}\" is published instead of \"{}\"",
+                    ambient.state(),
+                    lux,
+                    adjusted.to_theme_string(),
+                    solar_theme.to_theme_string()
+                )?;
+            }
+            adjusted
+        }
+        (None, Some(topic)) => {
+            writeln!(
+                out,
+                "  3. The ambient light sensor on {} makes the theme at most dark-soft below {} \
+                 lux and at least light-soft above {} lux; pass --lux to include a reading",
+                topic, publishing.ambient.lux_dim_below, publishing.ambient.lux_bright_above
+            )?;
+            caveats.push("the ambient light sensor may adjust it");
+            solar_theme
+        }
+        (None, None) => {
+            writeln!(out, "  3. No ambient light sensor configured")?;
+            solar_theme
+        }
+    };
+
+    let theme = match &args.theme_override {
+        Some(custom) => {
+            writeln!(
+                out,
+                "  4. Custom override \"{}\" replaces the solar theme until the phase changes",
+                custom
+            )?;
+            ThemeType::Custom(custom.clone())
+        }
+        None => {
+            writeln!(out, "  4. No custom override, using the solar theme")?;
+            adjusted
+        }
+    };
+
+    let hold = publishing.settle.max(publishing.min_dwell);
+    if hold.is_zero() {
+        writeln!(
+            out,
+            "  5. No settle or dwell period, so changes are published immediately"
+        )?;
+    } else {
+        writeln!(
+            out,
+            "  5. A change is published once wanted for {}s and after the previous theme was \
+             shown for {}s",
+            publishing.settle.num_seconds(),
+            publishing.min_dwell.num_seconds()
+        )?;
+        match state.since {
+            Some(since) if instant < since + hold => {
+                writeln!(
+                    out,
+                    "     This phase began {} ago, so the previous theme may be shown until {} UTC",
+                    format_duration(instant - since),
+                    (since + hold).format("%Y-%m-%d %H:%M:%S")
+                )?;
+                caveats.push("the change may still be settling");
+            }
+            _ => writeln!(
+                out,
+                "     This phase has lasted long enough to be published"
+            )?,
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Result: {} -> \"{}\" ({})",
+        theme.phase_name(),
+        theme.to_theme_string(),
+        theme.to_description()
+    )?;
+    if !caveats.is_empty() {
+        writeln!(
+            out,
+            "        Not necessarily the published theme: {}",
+            caveats.join(", and ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Format a duration as hours and minutes, e.g. `2h 05m`.
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,283 @@
+use crate::OverrideMessage;
+use crate::location::{BoxFuture, Location, LocationProvider, LocationSource, ProviderKind};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::time::Duration as StdDuration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, instrument};
+
+const WATCH_COMMAND: &[u8] = b"?WATCH={\"enable\":true,\"json\":true};\n";
+
+/// How long the startup provider waits for gpsd to report a fix.
+const FIX_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+// Only the fields of gpsd's TPV report that we care about
+#[derive(Debug, Deserialize)]
+struct GpsdReport {
+    class: String,
+    #[serde(default)]
+    mode: u8,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    alt: Option<f64>,
+    #[serde(rename = "altMSL")]
+    alt_msl: Option<f64>,
+}
+
+impl GpsdReport {
+    /// Returns the position if this is a TPV report with at least a 2D fix.
+    fn fix(&self) -> Option<Location> {
+        if self.class != "TPV" || self.mode < 2 {
+            return None;
+        }
+
+        Some(Location {
+            latitude: self.lat?,
+            longitude: self.lon?,
+            elevation: self.alt_msl.or(self.alt).unwrap_or(0.0),
+        })
+    }
+}
+
+async fn connect(addr: &str) -> Result<Lines<BufReader<TcpStream>>> {
+    debug!("Connecting to gpsd at {}", addr);
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("Failed to connect to gpsd at {}", addr))?;
+    stream
+        .write_all(WATCH_COMMAND)
+        .await
+        .context("Failed to send WATCH command to gpsd")?;
+
+    Ok(BufReader::new(stream).lines())
+}
+
+async fn next_fix(lines: &mut Lines<BufReader<TcpStream>>) -> Result<Location> {
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .context("Failed to read from gpsd")?
+    {
+        match serde_json::from_str::<GpsdReport>(&line) {
+            Ok(report) => {
+                if let Some(location) = report.fix() {
+                    return Ok(location);
+                }
+                debug!("Skipping gpsd {} report without fix", report.class);
+            }
+            Err(e) => debug!("Ignoring unparseable gpsd line: {}", e),
+        }
+    }
+
+    bail!("gpsd closed the connection")
+}
+
+/// Location provider that waits for the first fix reported by gpsd.
+pub struct GpsdProvider {
+    pub addr: String,
+}
+
+impl LocationProvider for GpsdProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Gpsd
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move {
+            let mut lines = connect(&self.addr).await?;
+            tokio::time::timeout(FIX_TIMEOUT, next_fix(&mut lines))
+                .await
+                .context("Timed out waiting for a gpsd fix")?
+        })
+    }
+}
+
+/// Follow gpsd reports and forward positions that moved more than `min_distance_m`.
+#[instrument(skip(initial, location_tx))]
+pub async fn watch(
+    addr: String,
+    initial: Location,
+    min_distance_m: f64,
+    location_tx: mpsc::Sender<OverrideMessage>,
+) -> Result<()> {
+    let mut last_location = initial;
+    let mut reconnect_delay = 1u64;
+    const MAX_RECONNECT_DELAY: u64 = 60;
+
+    loop {
+        let mut lines = match connect(&addr).await {
+            Ok(lines) => {
+                info!("✓ Watching gpsd at {}", addr);
+                reconnect_delay = 1;
+                lines
+            }
+            Err(e) => {
+                error!("{:#}, retrying in {} seconds", e, reconnect_delay);
+                tokio::time::sleep(StdDuration::from_secs(reconnect_delay)).await;
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
+            }
+        };
+
+        loop {
+            let location = match next_fix(&mut lines).await {
+                Ok(location) => location,
+                Err(e) => {
+                    error!("gpsd connection lost: {:#}", e);
+                    break;
+                }
+            };
+
+            let distance = last_location.distance_to(&location);
+            debug!(
+                "gpsd fix: lat={:.5}, lon={:.5} ({:.0}m from last)",
+                location.latitude, location.longitude, distance
+            );
+            if distance < min_distance_m {
+                continue;
+            }
+
+            info!(
+                "📍 Moved {:.0}m, updating location to lat={:.4}, lon={:.4}",
+                distance, location.latitude, location.longitude
+            );
+            location_tx
+                .send(OverrideMessage::SetLocation(
+                    location,
+                    LocationSource::Tracker,
+                ))
+                .await
+                .context("Override channel closed")?;
+            last_location = location;
+        }
+
+        tokio::time::sleep(StdDuration::from_secs(reconnect_delay)).await;
+        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    const VERSION: &str =
+        r#"{"class":"VERSION","release":"3.25","proto_major":3,"proto_minor":15}"#;
+    const SKY: &str = r#"{"class":"SKY","device":"/dev/ttyUSB0","nSat":12,"uSat":0}"#;
+
+    fn tpv(mode: u8, lat: f64, lon: f64) -> String {
+        format!(
+            r#"{{"class":"TPV","device":"/dev/ttyUSB0","mode":{},"lat":{},"lon":{},"alt":120.5,"altMSL":80.25}}"#,
+            mode, lat, lon
+        )
+    }
+
+    /// Serve `lines` to a single client after checking that it enabled watch mode.
+    async fn fake_gpsd(lines: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut command = vec![0; WATCH_COMMAND.len()];
+            stream.read_exact(&mut command).await.unwrap();
+            assert_eq!(command, WATCH_COMMAND);
+
+            for line in lines {
+                stream.write_all(line.as_bytes()).await.unwrap();
+                stream.write_all(b"\n").await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    fn report(json: &str) -> GpsdReport {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn fix_needs_tpv_with_2d_fix() {
+        assert_eq!(report(SKY).fix(), None);
+        assert_eq!(report(&tpv(0, 1.0, 2.0)).fix(), None);
+        assert_eq!(report(&tpv(1, 1.0, 2.0)).fix(), None);
+        assert_eq!(report(r#"{"class":"TPV","mode":2,"lat":1.0}"#).fix(), None);
+        assert_eq!(
+            report(r#"{"class":"TPV","mode":2,"lat":1.0,"lon":2.0}"#).fix(),
+            Some(Location {
+                latitude: 1.0,
+                longitude: 2.0,
+                elevation: 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn fix_prefers_altitude_above_mean_sea_level() {
+        assert_eq!(report(&tpv(3, 1.0, 2.0)).fix().unwrap().elevation, 80.25);
+        let without_msl = r#"{"class":"TPV","mode":3,"lat":1.0,"lon":2.0,"alt":120.5}"#;
+        assert_eq!(report(without_msl).fix().unwrap().elevation, 120.5);
+    }
+
+    #[tokio::test]
+    async fn provider_returns_first_fix() {
+        let addr = fake_gpsd(vec![
+            VERSION.to_string(),
+            SKY.to_string(),
+            "not json".to_string(),
+            tpv(1, 10.0, 10.0),
+            tpv(2, 69.65, 18.96),
+            tpv(3, 78.22, 15.65),
+        ])
+        .await;
+
+        let location = GpsdProvider { addr }.locate().await.unwrap();
+        assert_eq!((location.latitude, location.longitude), (69.65, 18.96));
+    }
+
+    #[tokio::test]
+    async fn provider_fails_when_gpsd_closes_without_fix() {
+        let addr = fake_gpsd(vec![VERSION.to_string(), tpv(1, 10.0, 10.0)]).await;
+        assert!(GpsdProvider { addr }.locate().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn watch_forwards_only_moves_beyond_threshold() {
+        // 0.001° of latitude is about 111m, 0.05° about 5.6km
+        let addr = fake_gpsd(vec![
+            VERSION.to_string(),
+            tpv(3, 52.001, 13.0),
+            tpv(3, 52.05, 13.0),
+            tpv(3, 52.051, 13.0),
+            tpv(1, 60.0, 13.0),
+            tpv(3, 52.0, 13.0),
+        ])
+        .await;
+
+        let initial = Location {
+            latitude: 52.0,
+            longitude: 13.0,
+            elevation: 0.0,
+        };
+        let (location_tx, mut location_rx) = mpsc::channel(10);
+        let watcher = tokio::spawn(watch(addr, initial, 1000.0, location_tx));
+
+        let mut forwarded = Vec::new();
+        while let Ok(Some(msg)) =
+            tokio::time::timeout(StdDuration::from_millis(500), location_rx.recv()).await
+        {
+            match msg {
+                OverrideMessage::SetLocation(location, LocationSource::Tracker) => {
+                    forwarded.push(location.latitude)
+                }
+                other => panic!("Unexpected message {:?}", other),
+            }
+        }
+        watcher.abort();
+
+        assert_eq!(forwarded, vec![52.05, 52.0]);
+    }
+}
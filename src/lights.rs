@@ -0,0 +1,122 @@
+use crate::solar::{self, SunPosition};
+use clap::Parser;
+use serde::Serialize;
+
+#[derive(Debug, Parser, Clone)]
+pub struct LightArgs {
+    /// Comma-separated Zigbee2MQTT or Home Assistant JSON light command topics to drive
+    /// from the sun, e.g. `zigbee2mqtt/desk_lamp/set`
+    #[arg(long, env = "LIGHT_TOPICS", value_delimiter = ',')]
+    pub light_topics: Vec<String>,
+
+    /// Brightness (1-254) with the sun down
+    #[arg(long, default_value = "40", env = "LIGHT_MIN_BRIGHTNESS", value_parser = clap::value_parser!(u8).range(1..=254))]
+    pub light_min_brightness: u8,
+
+    /// Brightness (1-254) in full daylight
+    #[arg(long, default_value = "254", env = "LIGHT_MAX_BRIGHTNESS", value_parser = clap::value_parser!(u8).range(1..=254))]
+    pub light_max_brightness: u8,
+
+    /// Seconds over which lights fade to each new setting
+    #[arg(long, default_value = "30", env = "LIGHT_TRANSITION_SECS")]
+    pub light_transition_secs: u32,
+
+    /// Seconds between light updates as the sun moves, `0` to only update them on startup
+    /// and when the location changes
+    #[arg(long, default_value = "120", env = "LIGHT_INTERVAL_SECS")]
+    pub light_interval_secs: u64,
+}
+
+/// A light command in the JSON schema shared by Zigbee2MQTT and Home Assistant.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LightCommand {
+    pub brightness: u8,
+    /// Color temperature in mireds
+    pub color_temp: u32,
+    pub transition: u32,
+}
+
+impl LightCommand {
+    /// The command that makes lights follow the sun at `position`.
+    pub fn for_sun(args: &LightArgs, position: SunPosition) -> Self {
+        let daylight = solar::daylight(position.elevation);
+        let (low, high) = (
+            args.light_min_brightness.min(args.light_max_brightness) as f64,
+            args.light_max_brightness.max(args.light_min_brightness) as f64,
+        );
+        let kelvin = solar::color_temperature(position.elevation);
+
+        Self {
+            brightness: (low + (high - low) * daylight).round() as u8,
+            color_temp: (1_000_000.0 / kelvin).round() as u32,
+            transition: args.light_transition_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(min: u8, max: u8) -> LightArgs {
+        LightArgs {
+            light_topics: Vec::new(),
+            light_min_brightness: min,
+            light_max_brightness: max,
+            light_transition_secs: 30,
+            light_interval_secs: 120,
+        }
+    }
+
+    fn sun_at(elevation: f64) -> SunPosition {
+        SunPosition {
+            elevation,
+            azimuth: 180.0,
+        }
+    }
+
+    #[test]
+    fn warm_and_dim_at_night() {
+        assert_eq!(
+            LightCommand::for_sun(&args(40, 254), sun_at(-30.0)),
+            LightCommand {
+                brightness: 40,
+                color_temp: 370,
+                transition: 30,
+            }
+        );
+    }
+
+    #[test]
+    fn cool_and_bright_in_full_day() {
+        assert_eq!(
+            LightCommand::for_sun(&args(40, 254), sun_at(60.0)),
+            LightCommand {
+                brightness: 254,
+                color_temp: 154,
+                transition: 30,
+            }
+        );
+    }
+
+    #[test]
+    fn brightness_is_halfway_at_the_horizon() {
+        assert_eq!(
+            LightCommand::for_sun(&args(40, 240), sun_at(0.0)).brightness,
+            140
+        );
+    }
+
+    #[test]
+    fn swaps_min_above_max() {
+        let swapped = args(254, 40);
+        assert_eq!(
+            LightCommand::for_sun(&swapped, sun_at(-30.0)).brightness,
+            40
+        );
+        assert_eq!(
+            LightCommand::for_sun(&swapped, sun_at(60.0)).brightness,
+            254
+        );
+    }
+}
@@ -0,0 +1,685 @@
+use crate::OverrideMessage;
+use crate::gpsd::GpsdProvider;
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::Duration as StdDuration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, error, info, instrument};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+const HTTP_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(alias = "lat")]
+    pub latitude: f64,
+    #[serde(alias = "lon", alias = "lng")]
+    pub longitude: f64,
+    #[serde(default, alias = "alt", alias = "altitude")]
+    pub elevation: f64,
+}
+
+impl Location {
+    /// Great-circle distance to another location in meters.
+    pub fn distance_to(&self, other: &Location) -> f64 {
+        const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct LocationArgs {
+    /// Latitude in degrees; together with --longitude this skips IP geolocation
+    #[arg(
+        long,
+        env = "LATITUDE",
+        requires = "longitude",
+        allow_hyphen_values = true
+    )]
+    pub latitude: Option<f64>,
+
+    /// Longitude in degrees; together with --latitude this skips IP geolocation
+    #[arg(
+        long,
+        env = "LONGITUDE",
+        requires = "latitude",
+        allow_hyphen_values = true
+    )]
+    pub longitude: Option<f64>,
+
+    /// Observer elevation in meters, used to refine solar event times
+    #[arg(long, env = "ELEVATION", allow_hyphen_values = true)]
+    pub elevation: Option<f64>,
+
+    /// Order in which location providers are tried; unconfigured providers are skipped
+    #[arg(
+        long,
+        env = "LOCATION_PROVIDERS",
+        value_delimiter = ',',
+        default_value = "static,file,gpsd,http,ip-api,cache"
+    )]
+    pub location_providers: Vec<ProviderKind>,
+
+    /// JSON or TOML file containing `latitude`, `longitude` and optionally `elevation`
+    #[arg(long, env = "LOCATION_FILE")]
+    pub location_file: Option<PathBuf>,
+
+    /// HTTP endpoint returning JSON with `lat`/`lon` or `latitude`/`longitude` fields
+    #[arg(long, env = "LOCATION_URL")]
+    pub location_url: Option<String>,
+
+    /// File used to remember the last successfully resolved location
+    #[arg(long, env = "LOCATION_CACHE")]
+    pub location_cache: Option<PathBuf>,
+
+    /// Re-resolve the location every N seconds; 0 disables periodic refresh
+    #[arg(long, default_value = "0", env = "LOCATION_REFRESH_SECS")]
+    pub location_refresh_secs: u64,
+
+    /// Use gpsd for the location and follow position changes while running
+    #[arg(long, env = "GPSD")]
+    pub gpsd: bool,
+
+    /// Address of the gpsd daemon
+    #[arg(long, default_value = "localhost:2947", env = "GPSD_ADDR")]
+    pub gpsd_addr: String,
+
+    /// Minimum distance in meters the position must move before the schedule is recomputed
+    #[arg(long, default_value = "1000", env = "GPSD_MIN_DISTANCE_METERS")]
+    pub gpsd_min_distance_meters: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProviderKind {
+    Static,
+    File,
+    Gpsd,
+    IpApi,
+    Http,
+    Cache,
+}
+
+impl ProviderKind {
+    /// Name used in logs, matching the `--location-providers` value.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProviderKind::Static => "static",
+            ProviderKind::File => "file",
+            ProviderKind::Gpsd => "gpsd",
+            ProviderKind::IpApi => "ip-api",
+            ProviderKind::Http => "http",
+            ProviderKind::Cache => "cache",
+        }
+    }
+}
+
+/// A source that can resolve the observer's location.
+pub trait LocationProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>>;
+}
+
+/// Where a location update came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocationSource {
+    /// The provider chain, re-run by the periodic refresh
+    Refresh,
+    /// The provider chain, re-run on a relocate request
+    Relocate,
+    /// A live position from gpsd or the MQTT location topic
+    Tracker,
+}
+
+/// Coordinates given on the command line or via environment variables.
+pub struct StaticProvider {
+    location: Location,
+}
+
+impl LocationProvider for StaticProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Static
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move { Ok(self.location) })
+    }
+}
+
+/// Coordinates read from a JSON or TOML file on disk.
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+impl LocationProvider for FileProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::File
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move { read_location_file(&self.path).await })
+    }
+}
+
+/// IP-based geolocation through ip-api.com.
+pub struct IpApiProvider {
+    client: reqwest::Client,
+}
+
+// Geolocation API integration
+#[derive(Debug, Deserialize)]
+struct IpApiResponse {
+    lat: f64,
+    lon: f64,
+}
+
+impl LocationProvider for IpApiProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::IpApi
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move {
+            // Use ip-api.com to get location based on IP
+            debug!("Fetching location from ip-api.com");
+            let response: IpApiResponse = self
+                .client
+                .get("http://ip-api.com/json/?fields=lat,lon")
+                .send()
+                .await
+                .context("Failed to fetch geolocation")?
+                .error_for_status()
+                .context("Geolocation request failed")?
+                .json()
+                .await
+                .context("Failed to parse geolocation response")?;
+            debug!(
+                "Received location: lat={}, lon={}",
+                response.lat, response.lon
+            );
+
+            Ok(Location {
+                latitude: response.lat,
+                longitude: response.lon,
+                elevation: 0.0,
+            })
+        })
+    }
+}
+
+/// A user-configured HTTP endpoint returning JSON coordinates.
+pub struct HttpProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl LocationProvider for HttpProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Http
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move {
+            debug!("Fetching location from {}", self.url);
+            self.client
+                .get(&self.url)
+                .send()
+                .await
+                .context("Failed to fetch location")?
+                .error_for_status()
+                .context("Location request failed")?
+                .json()
+                .await
+                .context("Failed to parse location response")
+        })
+    }
+}
+
+/// The last location successfully resolved by any other provider.
+pub struct CacheProvider {
+    path: PathBuf,
+}
+
+impl LocationProvider for CacheProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Cache
+    }
+
+    fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+        Box::pin(async move {
+            let contents = tokio::fs::read_to_string(&self.path)
+                .await
+                .with_context(|| format!("Failed to read {}", self.path.display()))?;
+            serde_json::from_str(&contents).context("Failed to parse cached location")
+        })
+    }
+}
+
+/// Ordered fallback chain of location providers.
+pub struct LocationChain {
+    providers: Vec<Box<dyn LocationProvider>>,
+    cache: Option<PathBuf>,
+    elevation: Option<f64>,
+}
+
+impl LocationChain {
+    pub fn from_args(args: &LocationArgs) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let mut providers: Vec<Box<dyn LocationProvider>> = Vec::new();
+        for kind in &args.location_providers {
+            match kind {
+                ProviderKind::Static => {
+                    if let (Some(latitude), Some(longitude)) = (args.latitude, args.longitude) {
+                        providers.push(Box::new(StaticProvider {
+                            location: Location {
+                                latitude,
+                                longitude,
+                                elevation: args.elevation.unwrap_or(0.0),
+                            },
+                        }));
+                    }
+                }
+                ProviderKind::File => {
+                    if let Some(path) = &args.location_file {
+                        providers.push(Box::new(FileProvider { path: path.clone() }));
+                    }
+                }
+                ProviderKind::Gpsd => {
+                    if args.gpsd {
+                        providers.push(Box::new(GpsdProvider {
+                            addr: args.gpsd_addr.clone(),
+                        }));
+                    }
+                }
+                ProviderKind::IpApi => {
+                    providers.push(Box::new(IpApiProvider {
+                        client: client.clone(),
+                    }));
+                }
+                ProviderKind::Http => {
+                    if let Some(url) = &args.location_url {
+                        providers.push(Box::new(HttpProvider {
+                            client: client.clone(),
+                            url: url.clone(),
+                        }));
+                    }
+                }
+                ProviderKind::Cache => {
+                    if let Some(path) = &args.location_cache {
+                        providers.push(Box::new(CacheProvider { path: path.clone() }));
+                    }
+                }
+            }
+        }
+
+        if providers.is_empty() {
+            bail!("No location providers configured");
+        }
+
+        Ok(Self {
+            providers,
+            cache: args.location_cache.clone(),
+            elevation: args.elevation,
+        })
+    }
+
+    /// Try each provider in order and return the first location found.
+    #[instrument(skip(self))]
+    pub async fn locate(&self) -> Result<Location> {
+        for provider in &self.providers {
+            let name = provider.kind().name();
+            debug!("Trying location provider: {}", name);
+            match provider.locate().await {
+                Ok(mut location) => {
+                    if let Some(elevation) = self.elevation {
+                        location.elevation = elevation;
+                    }
+                    info!("📍 Location resolved by {} provider", name);
+                    if provider.kind() != ProviderKind::Cache
+                        && let Some(path) = &self.cache
+                        && let Err(e) = write_cache(path, &location).await
+                    {
+                        error!("Failed to update location cache: {}", e);
+                    }
+                    return Ok(location);
+                }
+                Err(e) => {
+                    error!("Location provider {} failed: {:#}", name, e);
+                }
+            }
+        }
+
+        bail!("All location providers failed")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LocationMessageType {
+    #[serde(rename = "_type")]
+    kind: Option<String>,
+}
+
+/// Parse an OwnTracks `location` message or plain `{lat, lon}` JSON.
+pub fn parse_location_payload(payload: &str) -> Result<Location> {
+    let message: LocationMessageType =
+        serde_json::from_str(payload).context("Location payload is not a JSON object")?;
+    if let Some(kind) = message.kind
+        && kind != "location"
+    {
+        bail!("Not a location message: {}", kind);
+    }
+
+    serde_json::from_str(payload).context("Failed to parse location payload")
+}
+
+/// Re-run the provider chain periodically or when triggered, forwarding each result.
+#[instrument(skip_all)]
+pub async fn refresh(
+    chain: LocationChain,
+    interval: Option<StdDuration>,
+    mut trigger_rx: mpsc::Receiver<()>,
+    location_tx: mpsc::Sender<OverrideMessage>,
+) -> Result<()> {
+    let mut ticker =
+        interval.map(|period| tokio::time::interval_at(Instant::now() + period, period));
+
+    loop {
+        let source = tokio::select! {
+            _ = async {
+                match ticker.as_mut() {
+                    Some(ticker) => {
+                        ticker.tick().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            } => {
+                debug!("Periodic location refresh");
+                LocationSource::Refresh
+            }
+            trigger = trigger_rx.recv() => {
+                if trigger.is_none() {
+                    debug!("Location refresh trigger channel closed");
+                    return Ok(());
+                }
+                info!("🔄 Refreshing location on demand");
+                LocationSource::Relocate
+            }
+        };
+
+        match chain.locate().await {
+            Ok(location) => location_tx
+                .send(OverrideMessage::SetLocation(location, source))
+                .await
+                .context("Override channel closed")?,
+            Err(e) => error!("Location refresh failed: {:#}", e),
+        }
+    }
+}
+
+async fn write_cache(path: &Path, location: &Location) -> Result<()> {
+    debug!("Writing location cache to {}", path.display());
+    let contents = serde_json::to_string(location)?;
+    tokio::fs::write(path, contents)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+async fn read_location_file(path: &Path) -> Result<Location> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    parse_location_file(path, &contents)
+}
+
+/// Parse a location file as TOML when it has a `.toml` extension and as JSON otherwise.
+fn parse_location_file(path: &Path, contents: &str) -> Result<Location> {
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(contents).context("Failed to parse location file")
+    } else {
+        serde_json::from_str(contents).context("Failed to parse location file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_location_file() {
+        let contents = "latitude = 47.6062\nlongitude = -122.3321\nelevation = 56\n";
+        let location = parse_location_file(Path::new("home.toml"), contents).unwrap();
+        assert_eq!(
+            location,
+            Location {
+                latitude: 47.6062,
+                longitude: -122.3321,
+                elevation: 56.0,
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_toml_keys() {
+        let contents = "name = \"home\" # a comment\nlat = 52.52\nlon = 13.405\n";
+        let location = parse_location_file(Path::new("home.toml"), contents).unwrap();
+        assert_eq!((location.latitude, location.longitude), (52.52, 13.405));
+        assert_eq!(location.elevation, 0.0);
+    }
+
+    #[test]
+    fn ignores_keys_inside_toml_tables() {
+        let contents = "latitude = 1.0\nlongitude = 2.0\n[other]\nlatitude = 3.0\n";
+        let location = parse_location_file(Path::new("home.toml"), contents).unwrap();
+        assert_eq!((location.latitude, location.longitude), (1.0, 2.0));
+
+        let nested_only = "[home]\nlatitude = 1.0\nlongitude = 2.0\n";
+        assert!(parse_location_file(Path::new("home.toml"), nested_only).is_err());
+    }
+
+    #[test]
+    fn rejects_toml_without_coordinates() {
+        assert!(parse_location_file(Path::new("home.toml"), "latitude = 1.0\n").is_err());
+        assert!(parse_location_file(Path::new("home.toml"), "latitude = \"north\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_json_location_file() {
+        let contents = r#"{"lat": -33.87, "lng": 151.21, "altitude": 3}"#;
+        let location = parse_location_file(Path::new("home.json"), contents).unwrap();
+        assert_eq!(
+            location,
+            Location {
+                latitude: -33.87,
+                longitude: 151.21,
+                elevation: 3.0,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_owntracks_location() {
+        let payload =
+            r#"{"_type":"location","tid":"ph","lat":69.65,"lon":18.96,"alt":12,"tst":1781913600}"#;
+        let location = parse_location_payload(payload).unwrap();
+        assert_eq!(
+            location,
+            Location {
+                latitude: 69.65,
+                longitude: 18.96,
+                elevation: 12.0,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_plain_location_payloads() {
+        let cases = [
+            (r#"{"lat":47.6,"lon":-122.3}"#, (47.6, -122.3, 0.0)),
+            (
+                r#"{"latitude":-33.87,"longitude":151.21,"elevation":3}"#,
+                (-33.87, 151.21, 3.0),
+            ),
+            (r#"{"lat":1.0,"lng":2.0,"altitude":4.5}"#, (1.0, 2.0, 4.5)),
+        ];
+
+        for (payload, (latitude, longitude, elevation)) in cases {
+            let location = parse_location_payload(payload).unwrap();
+            assert_eq!(
+                (location.latitude, location.longitude, location.elevation),
+                (latitude, longitude, elevation),
+                "{}",
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_owntracks_messages() {
+        let rejected = [
+            r#"{"_type":"transition","event":"enter","lat":1.0,"lon":2.0}"#,
+            r#"{"_type":"waypoint","desc":"home","lat":1.0,"lon":2.0}"#,
+            r#"{"_type":"lwt","tst":1781913600}"#,
+            r#"{"lat":1.0}"#,
+            r#"[1.0, 2.0]"#,
+            "51.5,-0.12",
+            "",
+        ];
+
+        for payload in rejected {
+            assert!(parse_location_payload(payload).is_err(), "{}", payload);
+        }
+    }
+
+    const BERLIN: Location = Location {
+        latitude: 52.52,
+        longitude: 13.405,
+        elevation: 34.0,
+    };
+
+    /// A provider that is always unreachable, like ip-api during an outage at boot.
+    struct FailingProvider;
+
+    impl LocationProvider for FailingProvider {
+        fn kind(&self) -> ProviderKind {
+            ProviderKind::IpApi
+        }
+
+        fn locate(&self) -> BoxFuture<'_, Result<Location>> {
+            Box::pin(async { bail!("network unreachable") })
+        }
+    }
+
+    /// A fresh path in the temp directory for one test's cache file.
+    fn cache_path(test: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("theme-sender-{}-{}.json", test, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn chain(providers: Vec<Box<dyn LocationProvider>>, cache: Option<PathBuf>) -> LocationChain {
+        LocationChain {
+            providers,
+            cache,
+            elevation: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_cache_when_providers_fail() {
+        let path = cache_path("fallback");
+        std::fs::write(&path, serde_json::to_string(&BERLIN).unwrap()).unwrap();
+        let written = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let chain = chain(
+            vec![
+                Box::new(FailingProvider),
+                Box::new(CacheProvider { path: path.clone() }),
+            ],
+            Some(path.clone()),
+        );
+        assert_eq!(chain.locate().await.unwrap(), BERLIN);
+
+        // A location read from the cache is not written back
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            written
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn successful_lookup_writes_cache() {
+        let path = cache_path("write");
+        let chain = chain(
+            vec![
+                Box::new(FailingProvider),
+                Box::new(StaticProvider { location: BERLIN }),
+            ],
+            Some(path.clone()),
+        );
+        assert_eq!(chain.locate().await.unwrap(), BERLIN);
+
+        let cached = CacheProvider { path: path.clone() }.locate().await.unwrap();
+        assert_eq!(cached, BERLIN);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn first_working_provider_wins() {
+        let other = Location {
+            latitude: -33.87,
+            longitude: 151.21,
+            elevation: 0.0,
+        };
+        let chain = chain(
+            vec![
+                Box::new(StaticProvider { location: BERLIN }),
+                Box::new(StaticProvider { location: other }),
+            ],
+            None,
+        );
+        assert_eq!(chain.locate().await.unwrap(), BERLIN);
+    }
+
+    #[tokio::test]
+    async fn configured_elevation_replaces_resolved_one() {
+        let chain = LocationChain {
+            elevation: Some(100.0),
+            ..chain(vec![Box::new(StaticProvider { location: BERLIN })], None)
+        };
+        assert_eq!(chain.locate().await.unwrap().elevation, 100.0);
+    }
+
+    #[tokio::test]
+    async fn fails_when_every_provider_fails() {
+        let path = cache_path("missing");
+        let chain = chain(
+            vec![
+                Box::new(FailingProvider),
+                Box::new(CacheProvider { path: path.clone() }),
+            ],
+            Some(path.clone()),
+        );
+        let error = chain.locate().await.unwrap_err();
+        assert!(error.to_string().contains("All location providers failed"));
+        assert!(!path.exists());
+    }
+}
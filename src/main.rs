@@ -1,15 +1,46 @@
+mod ambient;
+mod clock;
+mod config;
+mod debounce;
+mod explain;
+mod gpsd;
+mod lights;
+mod location;
+mod mqtt;
+mod schedule;
+mod solar;
+mod theme;
+mod tls;
+
+use ambient::{AmbientArgs, AmbientLight};
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use clap::Parser;
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use clap::{Parser, Subcommand};
+use clock::{Clock, ClockArgs};
+use config::{Config, ConfigArgs};
+use debounce::{Decision, ThemeDebouncer};
+use explain::ExplainArgs;
+use lights::{LightArgs, LightCommand};
+use location::{Location, LocationArgs, LocationChain, LocationSource};
+use mqtt::{MqttVersion, Publisher};
+use schedule::ScheduleArgs;
+use serde::Serialize;
+use solar::SunPosition;
 use std::time::Duration as StdDuration;
-use sunrise::{Coordinates, DawnType, SolarDay, SolarEvent};
+use sunrise::Coordinates;
+use theme::{ThemeEngine, ThemeType};
+use tls::TlsArgs;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, instrument};
+
+/// Longest the main loop sleeps before re-checking the clock. Tokio's timer does not
+/// advance while the machine is suspended, so a long sleep could wake hours late.
+const MAX_TRANSITION_SLEEP: StdDuration = StdDuration::from_secs(60);
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    // Initialize tracing; logs go to stderr so subcommand output can be piped
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
@@ -17,224 +48,200 @@ async fn main() -> Result<()> {
         .init();
 
     let args = Args::parse();
+    let config = Config::from_args(&args.config)?;
+
+    // Get location from the first provider in the chain that succeeds
+    info!("Resolving location...");
+    let location_chain = LocationChain::from_args(&args.location)?;
+    let mut location = location_chain.locate().await?;
+    info!(
+        "Location: lat={:.4}, lon={:.4}, elevation={:.0}m",
+        location.latitude, location.longitude, location.elevation
+    );
+
+    let coordinates =
+        Coordinates::new(location.latitude, location.longitude).context("Invalid coordinates")?;
+    let build_engine = |coordinates: Coordinates, elevation: f64| {
+        ThemeEngine::new(coordinates, elevation)
+            .with_offsets(config.offsets.clone())
+            .with_bands(config.bands.clone())
+            .with_extra_phases(config.extra_phases.clone())
+    };
+    let mut engine = build_engine(coordinates, location.elevation);
+
+    let clock = Clock::from_args(&args.clock)?;
+    if let Clock::Simulated { start, speed, .. } = clock {
+        info!("⏩ Simulating from {} at {}x speed", start, speed);
+    }
+
+    match &args.command {
+        Some(Command::Schedule(schedule_args)) => {
+            return schedule::print(&engine, clock.now(), schedule_args);
+        }
+        Some(Command::Explain(explain_args)) => {
+            let instant = explain_args.at.unwrap_or_else(|| clock.now());
+            let publishing = explain::Publishing {
+                ambient: &args.ambient,
+                lux_topic: args.mqtt.mqtt_lux_topic.as_deref(),
+                settle: Duration::seconds(args.theme_settle_secs as i64),
+                min_dwell: Duration::seconds(args.min_dwell_secs as i64),
+            };
+            return explain::print(&engine, &location, instant, &publishing, explain_args);
+        }
+        None => {}
+    }
+
+    let mut ambient = AmbientLight::from_args(&args.ambient)?;
 
     info!("Starting theme sender...");
     info!("MQTT Host: {}", args.mqtt.mqtt_host);
     info!("MQTT Topic: {}", args.mqtt.mqtt_topic);
     info!("MQTT Override Topic: {}", args.mqtt.mqtt_override_topic);
     info!("MQTT Revert Topic: {}", args.mqtt.mqtt_revert_topic);
+    info!("MQTT Relocate Topic: {}", args.mqtt.mqtt_relocate_topic);
     debug!("MQTT Username: {:?}", args.mqtt.mqtt_username);
 
-    // Get location
-    info!("Fetching geolocation...");
-    let location = get_location().await?;
-    info!(
-        "Location: lat={:.4}, lon={:.4}",
-        location.latitude, location.longitude
-    );
-
-    let coordinates =
-        Coordinates::new(location.latitude, location.longitude).context("Invalid coordinates")?;
-
-    // Configure how often to publish the theme (in seconds)
+    // Configure how often to republish the theme (in seconds)
     let publish_interval = args.publish_interval_secs;
-    info!("Publishing theme every {} seconds", publish_interval);
+    if publish_interval == 0 {
+        info!("Publishing theme changes only");
+    } else {
+        info!("Publishing theme every {} seconds", publish_interval);
+    }
+    if args.mqtt.mqtt_retain {
+        info!("Theme messages are retained");
+    }
+    if let Some(state_topic) = &args.mqtt.mqtt_state_topic {
+        info!("MQTT State Topic: {}", state_topic);
+    }
+    if let Some(availability_topic) = &args.mqtt.mqtt_availability_topic {
+        info!("MQTT Availability Topic: {}", availability_topic);
+    }
 
-    // Create a channel for receiving custom theme overrides
+    // Create a channel for receiving custom theme overrides and location updates
     let (override_tx, mut override_rx) = mpsc::channel::<OverrideMessage>(10);
 
     // Spawn MQTT listener task for custom theme overrides
     info!("Spawning MQTT listener task...");
     let mqtt_args = args.mqtt.clone();
+    let listener_tx = override_tx.clone();
     tokio::spawn(async move {
-        if let Err(e) = mqtt_listener(mqtt_args, override_tx).await {
+        if let Err(e) = mqtt_listener(mqtt_args, listener_tx).await {
             error!("MQTT listener error: {}", e);
         }
     });
 
+    // Spawn location refresh task for periodic and on-demand re-geolocation
+    let (relocate_tx, relocate_rx) = mpsc::channel::<()>(1);
+    let refresh_interval = match args.location.location_refresh_secs {
+        0 => None,
+        secs => {
+            info!("Refreshing location every {} seconds", secs);
+            Some(StdDuration::from_secs(secs))
+        }
+    };
+    let location_tx = override_tx.clone();
+    tokio::spawn(async move {
+        if let Err(e) =
+            location::refresh(location_chain, refresh_interval, relocate_rx, location_tx).await
+        {
+            error!("Location refresh error: {}", e);
+        }
+    });
+
+    // Follow position changes reported by gpsd
+    if args.location.gpsd {
+        info!("Spawning gpsd watcher task...");
+        let gpsd_addr = args.location.gpsd_addr.clone();
+        let min_distance = args.location.gpsd_min_distance_meters;
+        let location_tx = override_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = gpsd::watch(gpsd_addr, location, min_distance, location_tx).await {
+                error!("gpsd watcher error: {}", e);
+            }
+        });
+    }
+
+    // One long-lived connection for every publish
+    let publisher = Publisher::new(&args.mqtt)?;
+
     // Publish current theme immediately on startup
-    let mut last_published_theme = {
+    let mut debouncer = {
         debug!("Calculating initial theme based on solar events");
-        let now = Utc::now();
-        let date = now.date_naive();
-        let solar_day = SolarDay::new(coordinates, date);
-
-        let mut events = [
-            (
-                ThemeType::AstronomicalDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Astronomical)),
-            ),
-            (
-                ThemeType::NauticalDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Nautical)),
-            ),
-            (
-                ThemeType::CivilDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Civil)),
-            ),
-            (
-                ThemeType::Sunrise,
-                solar_day.event_time(SolarEvent::Sunrise),
-            ),
-            (ThemeType::Day, solar_day.event_time(SolarEvent::Sunset)),
-            (
-                ThemeType::CivilDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Civil)),
-            ),
-            (
-                ThemeType::NauticalDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Nautical)),
-            ),
-            (
-                ThemeType::AstronomicalDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Astronomical)),
-            ),
-        ];
-
-        events.sort_by_key(|(_, time)| *time);
-
-        // Find current theme (the last event that has passed)
-        let current_theme = events
-            .iter()
-            .rev()
-            .find(|(_, time)| *time <= now)
-            .map(|(theme, _)| theme.clone())
-            .unwrap_or(ThemeType::Night);
+        let current_theme = engine.theme_at(clock.now()).theme;
 
         info!(
             "🌟 Publishing current theme on startup: {:?}",
             current_theme
         );
-        send_theme_update(&args.mqtt, &current_theme).await?;
-        Some(current_theme.clone())
+        let now = clock.now();
+        send_theme_update(
+            &args.mqtt,
+            &publisher,
+            &clock,
+            &engine,
+            &current_theme,
+            ThemeSource::Solar,
+            now,
+        )
+        .await?;
+        ThemeDebouncer::new(
+            current_theme,
+            now,
+            Duration::seconds(args.theme_settle_secs as i64),
+            Duration::seconds(args.min_dwell_secs as i64),
+        )
     };
 
+    if !args.lights.light_topics.is_empty() {
+        info!("💡 Driving lights: {}", args.lights.light_topics.join(", "));
+        if let Err(e) = send_light_update(&publisher, &args.lights, &clock, &engine).await {
+            error!("Light update error: {}", e);
+        }
+    }
+
+    let mut published_source = ThemeSource::Solar;
     let mut custom_override: Option<String> = None;
     let mut last_solar_theme: Option<ThemeType> = None;
-    let _immediate_check = false; // Flag to skip sleep and check immediately
-
-    loop {
-        debug!("Starting new publish cycle");
-        let now = Utc::now();
-        let date = now.date_naive();
-
-        // Calculate all solar events for today
-        let solar_day = SolarDay::new(coordinates, date);
-
-        let mut events = vec![
-            (
-                ThemeType::AstronomicalDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Astronomical)),
-            ),
-            (
-                ThemeType::NauticalDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Nautical)),
-            ),
-            (
-                ThemeType::CivilDawn,
-                solar_day.event_time(SolarEvent::Dawn(DawnType::Civil)),
-            ),
-            (
-                ThemeType::Sunrise,
-                solar_day.event_time(SolarEvent::Sunrise),
-            ),
-            (ThemeType::Day, solar_day.event_time(SolarEvent::Sunset)),
-            (
-                ThemeType::CivilDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Civil)),
-            ),
-            (
-                ThemeType::NauticalDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Nautical)),
-            ),
-            (
-                ThemeType::AstronomicalDusk,
-                solar_day.event_time(SolarEvent::Dusk(DawnType::Astronomical)),
-            ),
-            (
-                ThemeType::Night,
-                DateTime::from_timestamp(now.timestamp() + 86400, 0)
-                    .unwrap()
-                    .with_timezone(&Utc),
-            ), // Next day midnight
-        ];
-
-        // Sort by time
-        events.sort_by_key(|(_, time)| *time);
-
-        // Print today's schedule
-        info!("Today's schedule:");
-        for (theme, time) in &events {
-            if time.date_naive() == date {
-                info!("  {} - {:?}", time.format("%H:%M:%S"), theme);
-            }
-        }
+    let mut logged_schedule: Option<(NaiveDate, Location)> = None;
+    // Once a tracker reports a position, periodic provider refreshes no longer replace it
+    let mut following_tracker = false;
+
+    // Periodic republish of the current theme, unless disabled
+    let mut heartbeat = periodic(&clock, publish_interval);
+
+    // Lights follow the sun's continuous movement on their own schedule
+    let mut light_timer = if args.lights.light_topics.is_empty() {
+        None
+    } else {
+        periodic(&clock, args.lights.light_interval_secs)
+    };
 
-        // Check for custom theme override messages
-        // Process all pending messages before continuing
-        let mut theme_changed = false;
-        loop {
-            match override_rx.try_recv() {
-                Ok(msg) => {
-                    match msg {
-                        OverrideMessage::SetTheme(theme) => {
-                            info!("🎭 Received custom theme override: {}", theme);
-                            debug!("Setting custom_override to: {}", theme);
+    // Stop on Ctrl-C or SIGTERM so the availability topic can be set offline
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
-                            // Check if this is actually a change
-                            if custom_override.as_ref() != Some(&theme) {
-                                custom_override = Some(theme.clone());
-                                theme_changed = true;
-
-                                // Publish immediately
-                                let new_theme = ThemeType::Custom(theme);
-                                info!("🎨 Publishing new custom theme immediately");
-                                send_theme_update(&args.mqtt, &new_theme).await?;
-                                last_published_theme = Some(new_theme);
-                            } else {
-                                debug!("Custom theme unchanged, skipping republish");
-                            }
-                        }
-                        OverrideMessage::Revert => {
-                            info!("🔄 Received revert message, clearing custom override");
-                            debug!("Clearing custom_override");
-
-                            if custom_override.is_some() {
-                                custom_override = None;
-                                theme_changed = true;
-
-                                // Publish current solar theme immediately
-                                let solar_theme = events
-                                    .iter()
-                                    .rev()
-                                    .find(|(_, time)| *time <= now)
-                                    .map(|(theme, _)| theme.clone())
-                                    .unwrap_or(ThemeType::Night);
-                                info!("🎨 Publishing solar theme immediately: {:?}", solar_theme);
-                                send_theme_update(&args.mqtt, &solar_theme).await?;
-                                last_published_theme = Some(solar_theme);
-                            }
-                        }
-                    }
-                }
-                Err(mpsc::error::TryRecvError::Empty) => {
-                    // No more messages, continue
-                    break;
-                }
-                Err(mpsc::error::TryRecvError::Disconnected) => {
-                    error!("Override channel disconnected! MQTT listener may have crashed.");
-                    break;
-                }
+    loop {
+        debug!("Evaluating current theme");
+        let now = clock.now();
+        let today = engine.local_date(now);
+
+        // Print today's schedule whenever the day or location changes
+        if logged_schedule != Some((today, location)) {
+            info!("Today's schedule (UTC):");
+            for transition in engine.transitions(today) {
+                info!(
+                    "  {} - {}",
+                    transition.time.format("%H:%M:%S"),
+                    transition.theme.phase_name()
+                );
             }
+            logged_schedule = Some((today, location));
         }
 
         // Determine what theme to use
-        let solar_theme = events
-            .iter()
-            .rev()
-            .find(|(_, time)| *time <= now)
-            .map(|(theme, _)| theme.clone())
-            .unwrap_or(ThemeType::Night);
+        let state = engine.theme_at(now);
+        let solar_theme = state.theme;
         debug!("Current solar theme: {:?}", solar_theme);
 
         // Check if solar theme has changed (which would clear the override)
@@ -258,35 +265,222 @@ async fn main() -> Result<()> {
             last_solar_theme = Some(solar_theme.clone());
         }
 
-        let current_theme = if let Some(ref custom) = custom_override {
+        let adjusted = ambient.adjust(solar_theme.clone());
+        let (current_theme, source) = if let Some(ref custom) = custom_override {
             debug!("Using custom theme: {}", custom);
-            ThemeType::Custom(custom.clone())
+            (ThemeType::Custom(custom.clone()), ThemeSource::Override)
+        } else if adjusted != solar_theme {
+            debug!(
+                "Using solar theme adjusted for {:?} room: {:?}",
+                ambient.state(),
+                adjusted
+            );
+            (adjusted, ThemeSource::Ambient)
         } else {
             debug!("Using solar theme: {:?}", solar_theme);
-            solar_theme
+            (solar_theme, ThemeSource::Solar)
         };
 
-        // Publish if theme has changed or it's time for periodic update
-        if last_published_theme.as_ref() != Some(&current_theme) {
-            info!("🎨 Theme changed to {:?}", current_theme);
-            send_theme_update(&args.mqtt, &current_theme).await?;
-            last_published_theme = Some(current_theme.clone());
-        } else {
-            info!("♻️  Republishing current theme: {:?}", current_theme);
-            send_theme_update(&args.mqtt, &current_theme).await?;
+        // Publish once the changed theme has settled
+        let hold_until = match debouncer.update(&current_theme, now) {
+            Decision::Steady => {
+                published_source = source;
+                None
+            }
+            Decision::Publish(theme) => {
+                info!("🎨 Theme changed to {:?}", theme);
+                published_source = source;
+                if let Err(e) =
+                    send_theme_update(&args.mqtt, &publisher, &clock, &engine, &theme, source, now)
+                        .await
+                {
+                    error!("Theme update error: {}", e);
+                }
+                None
+            }
+            Decision::Hold(until) => {
+                debug!(
+                    "Holding {:?} until {} before switching to {:?}",
+                    debouncer.published(),
+                    until,
+                    current_theme
+                );
+                Some(until)
+            }
+        };
+
+        // Sleep until the next solar transition; without one (polar day or night),
+        // re-evaluate hourly as the theme then follows the sun's elevation. Long sleeps
+        // are split up so a suspended machine catches up within a minute of resuming
+        let next_transition = state
+            .next
+            .map(|transition| transition.time)
+            .unwrap_or(now + Duration::hours(1));
+        debug!("Next solar transition at {}", next_transition);
+        let wake_at = hold_until.map_or(next_transition, |until| until.min(next_transition));
+        let transition_sleep = clock.real_duration(wake_at - now).min(MAX_TRANSITION_SLEEP);
+
+        tokio::select! {
+            Some(msg) = override_rx.recv() => {
+                match msg {
+                    OverrideMessage::SetTheme(theme) => {
+                        info!("🎭 Received custom theme override: {}", theme);
+
+                        // Check if this is actually a change
+                        if custom_override.as_ref() != Some(&theme) {
+                            debug!("Setting custom_override to: {}", theme);
+                            custom_override = Some(theme);
+                        } else {
+                            debug!("Custom theme unchanged, skipping republish");
+                        }
+                    }
+                    OverrideMessage::Revert => {
+                        info!("🔄 Received revert message, clearing custom override");
+                        debug!("Clearing custom_override");
+                        custom_override = None;
+                    }
+                    OverrideMessage::Relocate => {
+                        info!("📍 Received relocate request");
+                        if relocate_tx.try_send(()).is_err() {
+                            debug!("Location refresh already pending");
+                        }
+                    }
+                    OverrideMessage::Lux(lux) => {
+                        debug!("Ambient light: {} lux", lux);
+                        if ambient.update(lux) {
+                            info!("🔆 Room is now {:?} at {} lux", ambient.state(), lux);
+                        }
+                    }
+                    OverrideMessage::SetLocation(_, LocationSource::Refresh) if following_tracker => {
+                        debug!("Keeping the tracked position over the periodic location refresh");
+                    }
+                    OverrideMessage::SetLocation(new_location, source) => {
+                        match Coordinates::new(new_location.latitude, new_location.longitude) {
+                            Some(_) if new_location == location => {
+                                following_tracker |= source == LocationSource::Tracker;
+                                debug!("Location unchanged, keeping current schedule");
+                            }
+                            Some(new_coordinates) => {
+                                following_tracker |= source == LocationSource::Tracker;
+                                info!(
+                                    "📍 Location updated: lat={:.4}, lon={:.4}",
+                                    new_location.latitude, new_location.longitude
+                                );
+                                location = new_location;
+                                engine = build_engine(new_coordinates, location.elevation);
+                                if let Err(e) =
+                                    send_light_update(&publisher, &args.lights, &clock, &engine)
+                                        .await
+                                {
+                                    error!("Light update error: {}", e);
+                                }
+                            }
+                            None => {
+                                error!("Ignoring invalid location update: {:?}", new_location);
+                            }
+                        }
+                    }
+                }
+            }
+            _ = tokio::time::sleep(transition_sleep) => {
+                if clock.now() < wake_at {
+                    debug!("Re-checking the clock before {}", wake_at);
+                    continue;
+                }
+                debug!("Woke for solar transition or settled theme at {}", wake_at);
+            }
+            _ = tick(&mut heartbeat) => {
+                let published = debouncer.published();
+                info!("♻️  Republishing current theme: {:?}", published);
+                if let Err(e) = send_theme_update(
+                    &args.mqtt,
+                    &publisher,
+                    &clock,
+                    &engine,
+                    published,
+                    published_source,
+                    debouncer.published_at(),
+                )
+                .await
+                {
+                    error!("Theme update error: {}", e);
+                }
+            }
+            _ = tick(&mut light_timer) => {
+                if let Err(e) = send_light_update(&publisher, &args.lights, &clock, &engine).await {
+                    error!("Light update error: {}", e);
+                }
+            }
+            _ = &mut shutdown => {
+                info!("👋 Shutting down");
+                break;
+            }
         }
+    }
 
-        // Wait for the configured interval before next check
-        // If we just published due to an override change, skip the sleep
-        // and immediately loop to check for more messages
-        if theme_changed {
-            debug!(
-                "Theme was changed by override/revert, continuing immediately to check for more messages"
-            );
-            // Don't sleep, just continue the loop
-        } else {
-            debug!("Waiting {} seconds until next check...", publish_interval);
-            tokio::time::sleep(StdDuration::from_secs(publish_interval)).await;
+    publisher.shutdown().await
+}
+
+/// A timer firing every `secs` seconds of clock time, first after one period, or `None`
+/// when disabled with `0`.
+fn periodic(clock: &Clock, secs: u64) -> Option<tokio::time::Interval> {
+    (secs > 0).then(|| {
+        let period = clock.real_duration(Duration::seconds(secs as i64));
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    })
+}
+
+/// Wait for the next tick of an optional timer; a disabled timer never fires.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolve once the process is asked to stop.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => error!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Why the published theme was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ThemeSource {
+    Solar,
+    Ambient,
+    Override,
+}
+
+impl ThemeSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            ThemeSource::Solar => "solar",
+            ThemeSource::Ambient => "ambient",
+            ThemeSource::Override => "override",
         }
     }
 }
@@ -295,6 +489,9 @@ async fn main() -> Result<()> {
 enum OverrideMessage {
     SetTheme(String),
     Revert,
+    Relocate,
+    SetLocation(Location, LocationSource),
+    Lux(f64),
 }
 
 #[instrument(skip(override_tx))]
@@ -310,12 +507,13 @@ async fn mqtt_listener(
         const MAX_RECONNECT_DELAY: u64 = 60; // Cap at 60 seconds
 
         loop {
+            if override_tx.is_closed() {
+                break;
+            }
+
             debug!("Creating MQTT client for listener");
             // Create MQTT client
-            let create_opts = paho_mqtt::CreateOptionsBuilder::new()
-                .server_uri(&args.mqtt_host)
-                .client_id("theme-sender-listener")
-                .finalize();
+            let create_opts = mqtt::create_options(&args, "theme-sender-listener");
 
             let client = match paho_mqtt::Client::new(create_opts) {
                 Ok(c) => c,
@@ -329,18 +527,12 @@ async fn mqtt_listener(
 
             // Set up connection options with auto-reconnect
             debug!("Configuring MQTT connection for listener");
-            let mut conn_opts_builder = paho_mqtt::ConnectOptionsBuilder::new();
-            conn_opts_builder
-                .keep_alive_interval(StdDuration::from_secs(20))
-                .clean_session(false)
-                .automatic_reconnect(StdDuration::from_secs(1), StdDuration::from_secs(60));
-
-            if let (Some(username), Some(password)) = (&args.mqtt_username, &args.mqtt_password) {
-                debug!("Using MQTT authentication for listener");
-                conn_opts_builder.user_name(username).password(password);
-            }
-
-            let conn_opts = conn_opts_builder.finalize();
+            // Keep the session, and with it the subscriptions, across reconnects
+            let mut conn_opts_builder = mqtt::connect_options(&args)?;
+            mqtt::keep_session(&mut conn_opts_builder, args.mqtt_version)?;
+            let conn_opts = conn_opts_builder
+                .automatic_reconnect(StdDuration::from_secs(1), StdDuration::from_secs(60))
+                .finalize();
 
             // Start consumer before connecting
             let rx = client.start_consuming();
@@ -375,11 +567,47 @@ async fn mqtt_listener(
                 continue;
             }
 
+            // Subscribe to relocate topic
+            debug!(
+                "Subscribing to relocate topic: {}",
+                args.mqtt_relocate_topic
+            );
+            if let Err(e) = client.subscribe(&args.mqtt_relocate_topic, 1) {
+                error!("Failed to subscribe to relocate topic: {}", e);
+                std::thread::sleep(StdDuration::from_secs(reconnect_delay));
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
+            }
+
             info!(
-                "✓ Subscribed to {} and {}",
-                args.mqtt_override_topic, args.mqtt_revert_topic
+                "✓ Subscribed to {}, {} and {}",
+                args.mqtt_override_topic, args.mqtt_revert_topic, args.mqtt_relocate_topic
             );
 
+            // Subscribe to location topic (OwnTracks / Home Assistant)
+            if let Some(location_topic) = &args.mqtt_location_topic {
+                debug!("Subscribing to location topic: {}", location_topic);
+                if let Err(e) = client.subscribe(location_topic, 1) {
+                    error!("Failed to subscribe to location topic: {}", e);
+                    std::thread::sleep(StdDuration::from_secs(reconnect_delay));
+                    reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+                info!("✓ Subscribed to location topic {}", location_topic);
+            }
+
+            // Subscribe to ambient light sensor topic
+            if let Some(lux_topic) = &args.mqtt_lux_topic {
+                debug!("Subscribing to lux topic: {}", lux_topic);
+                if let Err(e) = client.subscribe(lux_topic, 1) {
+                    error!("Failed to subscribe to lux topic: {}", e);
+                    std::thread::sleep(StdDuration::from_secs(reconnect_delay));
+                    reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+                info!("✓ Subscribed to lux topic {}", lux_topic);
+            }
+
             // Reset reconnect delay on successful connection
             reconnect_delay = 1;
 
@@ -397,11 +625,57 @@ async fn mqtt_listener(
                             "Received MQTT message on topic '{}' with payload '{}'",
                             topic, payload
                         );
+                        for (key, value) in msg.properties().user_iter() {
+                            debug!("User property {}={}", key, value);
+                        }
+                        let content_type = msg
+                            .properties()
+                            .get_string(paho_mqtt::PropertyCode::ContentType);
+                        if !mqtt::is_text_content_type(content_type.as_deref()) {
+                            debug!(
+                                "Ignoring {} message on topic {}",
+                                content_type.unwrap_or_default(),
+                                topic
+                            );
+                            continue;
+                        }
 
                         let override_msg = if topic == args.mqtt_revert_topic {
                             OverrideMessage::Revert
-                        } else {
+                        } else if topic == args.mqtt_relocate_topic {
+                            OverrideMessage::Relocate
+                        } else if args
+                            .mqtt_lux_topic
+                            .as_deref()
+                            .is_some_and(|filter| mqtt::topic_matches(filter, topic))
+                        {
+                            match ambient::parse_lux_payload(&payload) {
+                                Ok(lux) => OverrideMessage::Lux(lux),
+                                Err(e) => {
+                                    debug!("Ignoring lux message: {:#}", e);
+                                    continue;
+                                }
+                            }
+                        } else if args
+                            .mqtt_location_topic
+                            .as_deref()
+                            .is_some_and(|filter| mqtt::topic_matches(filter, topic))
+                        {
+                            match location::parse_location_payload(&payload) {
+                                Ok(location) => {
+                                    OverrideMessage::SetLocation(location, LocationSource::Tracker)
+                                }
+                                Err(e) => {
+                                    debug!("Ignoring location message: {:#}", e);
+                                    continue;
+                                }
+                            }
+                        } else if topic == args.mqtt_override_topic {
                             OverrideMessage::SetTheme(payload.clone())
+                        } else {
+                            // Left over in the persistent session from an earlier configuration
+                            debug!("Ignoring message on unexpected topic {}", topic);
+                            continue;
                         };
 
                         debug!("Parsed as: {:?}", override_msg);
@@ -419,7 +693,15 @@ async fn mqtt_listener(
                         break;
                     }
                     Err(_) => {
-                        // Timeout is normal, check connection status
+                        // Timeout is normal, check whether the main loop has stopped
+                        if override_tx.is_closed() {
+                            debug!("Main loop stopped, disconnecting MQTT listener");
+                            let _ = client.disconnect(None);
+                            connection_lost = false;
+                            break;
+                        }
+
+                        // and the connection status
                         if !client.is_connected() {
                             error!("MQTT connection lost, attempting to reconnect...");
                             connection_lost = true;
@@ -451,11 +733,24 @@ async fn mqtt_listener(
     Ok(())
 }
 
-#[instrument(skip(args))]
-async fn send_theme_update(args: &ThemeMqttArgs, theme: &ThemeType) -> Result<()> {
+/// Publish `theme`, shown since `since`, to the theme topic and the state topic if set.
+#[instrument(skip(args, publisher, clock, engine))]
+async fn send_theme_update(
+    args: &ThemeMqttArgs,
+    publisher: &Publisher,
+    clock: &Clock,
+    engine: &ThemeEngine,
+    theme: &ThemeType,
+    source: ThemeSource,
+    since: DateTime<Utc>,
+) -> Result<()> {
+    let now = clock.now();
     let payload = ThemePayload {
         theme: theme.to_theme_string(),
-        data: Utc::now(),
+        data: now,
+        sun: args
+            .publish_sun_position
+            .then(|| SunFields::new(engine.sun_position(now))),
     };
 
     info!(
@@ -465,145 +760,176 @@ async fn send_theme_update(args: &ThemeMqttArgs, theme: &ThemeType) -> Result<()
     );
     debug!("Theme payload: {:?}", payload);
 
-    let payload_json = serde_json::to_string(&payload)?;
-
-    // Retry logic with exponential backoff
-    let mut retry_delay = 1u64;
-    const MAX_RETRY_DELAY: u64 = 30;
-    const MAX_RETRIES: u32 = 5;
+    let phase = match theme {
+        ThemeType::Custom(_) => None,
+        solar => Some(solar.phase_name()),
+    };
+    let mut user_properties = vec![("source", source.as_str())];
+    if let Some(phase) = &phase {
+        user_properties.push(("phase", phase));
+    }
 
-    for attempt in 1..=MAX_RETRIES {
-        match try_send_mqtt(args, &payload_json, attempt).await {
-            Ok(()) => {
-                info!("✓ Theme update sent successfully");
-                return Ok(());
-            }
-            Err(e) if attempt < MAX_RETRIES => {
-                error!(
-                    "Failed to send theme update (attempt {}/{}): {}",
-                    attempt, MAX_RETRIES, e
-                );
-                debug!("Retrying in {} seconds...", retry_delay);
-                tokio::time::sleep(StdDuration::from_secs(retry_delay)).await;
-                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
-            }
-            Err(e) => {
-                error!(
-                    "Failed to send theme update after {} attempts: {}",
-                    MAX_RETRIES, e
-                );
-                return Err(e);
-            }
-        }
+    let payload_json = serde_json::to_string(&payload)?;
+    let mut messages = vec![mqtt::json_message(
+        args,
+        &args.mqtt_topic,
+        payload_json,
+        args.mqtt_retain,
+        args.mqtt_message_expiry_secs,
+        &user_properties,
+    )?];
+
+    if let Some(state_topic) = &args.mqtt_state_topic {
+        let next = engine.theme_at(now).next;
+        let state = StatePayload {
+            theme: payload.theme,
+            phase: phase.clone(),
+            source,
+            description: theme.to_description(),
+            since,
+            updated: now,
+            next_transition: next.as_ref().map(|transition| transition.time),
+            next_phase: next.map(|transition| transition.theme.phase_name()),
+        };
+        debug!("State payload: {:?}", state);
+        messages.push(mqtt::json_message(
+            args,
+            state_topic,
+            serde_json::to_string(&state)?,
+            true,
+            None,
+            &user_properties,
+        )?);
     }
 
-    Err(anyhow::anyhow!(
-        "Failed to send theme update after all retries"
-    ))
+    publisher
+        .publish_with_retry(&messages, "theme update")
+        .await
 }
 
-async fn try_send_mqtt(args: &ThemeMqttArgs, payload_json: &str, attempt: u32) -> Result<()> {
-    debug!("Attempting to send MQTT message (attempt {})", attempt);
-
-    // Create MQTT client
-    let create_opts = paho_mqtt::CreateOptionsBuilder::new()
-        .server_uri(&args.mqtt_host)
-        .client_id("theme-sender")
-        .finalize();
-
-    let client = paho_mqtt::Client::new(create_opts).context("Failed to create MQTT client")?;
-
-    // Set up connection options with automatic reconnect
-    let mut conn_opts_builder = paho_mqtt::ConnectOptionsBuilder::new();
-    conn_opts_builder
-        .keep_alive_interval(StdDuration::from_secs(20))
-        .automatic_reconnect(StdDuration::from_secs(1), StdDuration::from_secs(30));
-
-    if let (Some(username), Some(password)) = (&args.mqtt_username, &args.mqtt_password) {
-        debug!("Using MQTT authentication");
-        conn_opts_builder.user_name(username).password(password);
+/// Point the configured lights at the sun's current position.
+#[instrument(skip_all)]
+async fn send_light_update(
+    publisher: &Publisher,
+    lights: &LightArgs,
+    clock: &Clock,
+    engine: &ThemeEngine,
+) -> Result<()> {
+    if lights.light_topics.is_empty() {
+        return Ok(());
     }
 
-    let conn_opts = conn_opts_builder.finalize();
-
-    // Connect
-    client
-        .connect(conn_opts)
-        .context("Failed to connect to MQTT broker")?;
-
-    // Publish message
-    debug!("Publishing to topic {}: {}", args.mqtt_topic, payload_json);
-    let msg = paho_mqtt::Message::new(&args.mqtt_topic, payload_json, 1);
-    client.publish(msg).context("Failed to publish message")?;
+    let command = LightCommand::for_sun(lights, engine.sun_position(clock.now()));
+    info!(
+        "💡 Sending light update: brightness {}, color temperature {} mireds",
+        command.brightness, command.color_temp
+    );
 
-    // Disconnect
-    debug!("Disconnecting from MQTT broker");
-    client
-        .disconnect(None)
-        .context("Failed to disconnect from MQTT broker")?;
+    let payload_json = serde_json::to_string(&command)?;
+    let messages: Vec<_> = lights
+        .light_topics
+        .iter()
+        .map(|topic| paho_mqtt::Message::new(topic, payload_json.as_str(), 1))
+        .collect();
+    publisher
+        .publish_with_retry(&messages, "light update")
+        .await
+}
 
-    Ok(())
+#[derive(Debug, Serialize, Clone)]
+struct ThemePayload {
+    theme: String,
+    data: DateTime<Utc>,
+    #[serde(flatten)]
+    sun: Option<SunFields>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum ThemeType {
-    Night,
-    AstronomicalDawn,
-    NauticalDawn,
-    CivilDawn,
-    Sunrise,
-    Day,
-    CivilDusk,
-    NauticalDusk,
-    AstronomicalDusk,
-    Custom(String),
+/// Retained summary of the published theme for clients that start late.
+#[derive(Debug, Serialize, Clone)]
+struct StatePayload {
+    theme: String,
+    /// Solar phase behind the theme, or `None` for a custom override
+    phase: Option<String>,
+    source: ThemeSource,
+    description: &'static str,
+    /// When the theme was first published
+    since: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    next_transition: Option<DateTime<Utc>>,
+    next_phase: Option<String>,
 }
 
-impl ThemeType {
-    fn to_theme_string(&self) -> String {
-        match self {
-            ThemeType::Night => "dark".to_string(),
-            ThemeType::AstronomicalDawn => "dark-dimmed".to_string(),
-            ThemeType::NauticalDawn => "dark-soft".to_string(),
-            ThemeType::CivilDawn => "light-soft".to_string(),
-            ThemeType::Sunrise => "light".to_string(),
-            ThemeType::Day => "light".to_string(),
-            ThemeType::CivilDusk => "light-soft".to_string(),
-            ThemeType::NauticalDusk => "dark-soft".to_string(),
-            ThemeType::AstronomicalDusk => "dark-dimmed".to_string(),
-            ThemeType::Custom(theme) => theme.clone(),
-        }
-    }
+/// Continuous sun position and brightness, so clients can interpolate between themes.
+#[derive(Debug, Serialize, Clone)]
+struct SunFields {
+    /// Degrees above the horizon
+    elevation: f64,
+    /// Degrees clockwise from true north
+    azimuth: f64,
+    /// From 0.0 (dark) to 1.0 (full daylight)
+    daylight: f64,
+    /// Suggested color temperature in Kelvin
+    color_temperature: u32,
+}
 
-    fn to_description(&self) -> &'static str {
-        match self {
-            ThemeType::Night => "Full night - stars visible",
-            ThemeType::AstronomicalDawn => "Astronomical dawn - faint light appears in sky",
-            ThemeType::NauticalDawn => "Nautical dawn - horizon becomes visible",
-            ThemeType::CivilDawn => "Civil dawn - enough light for outdoor activities",
-            ThemeType::Sunrise => "Sunrise - sun breaks the horizon",
-            ThemeType::Day => "Full daylight",
-            ThemeType::CivilDusk => "Civil dusk - sun below horizon, still light out",
-            ThemeType::NauticalDusk => "Nautical dusk - darker, horizon still visible",
-            ThemeType::AstronomicalDusk => "Astronomical dusk - fading light in sky",
-            ThemeType::Custom(_theme) => "Custom theme override",
+impl SunFields {
+    fn new(position: SunPosition) -> Self {
+        Self {
+            elevation: round_to(position.elevation, 2),
+            azimuth: round_to(position.azimuth, 2),
+            daylight: round_to(solar::daylight(position.elevation), 3),
+            color_temperature: solar::color_temperature(position.elevation).round() as u32,
         }
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
-struct ThemePayload {
-    theme: String,
-    data: DateTime<Utc>,
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
 }
 
 #[derive(Debug, Parser)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[command(flatten)]
     mqtt: ThemeMqttArgs,
 
+    /// Seconds between republishes of the current theme, `0` to publish changes only
     #[arg(long, default_value = "300", env = "PUBLISH_INTERVAL_SECS")]
     publish_interval_secs: u64,
+
+    /// Seconds a new theme must stay wanted before it is published
+    #[arg(long, default_value = "0", env = "THEME_SETTLE_SECS")]
+    theme_settle_secs: u64,
+
+    /// Minimum seconds a published theme stays before it may change again
+    #[arg(long, default_value = "0", env = "MIN_DWELL_SECS")]
+    min_dwell_secs: u64,
+
+    #[command(flatten)]
+    location: LocationArgs,
+
+    #[command(flatten)]
+    clock: ClockArgs,
+
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(flatten)]
+    lights: LightArgs,
+
+    #[command(flatten)]
+    ambient: AmbientArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print upcoming theme transitions instead of running the daemon
+    Schedule(ScheduleArgs),
+    /// Explain which theme applies at an instant and why
+    Explain(ExplainArgs),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -617,9 +943,32 @@ struct ThemeMqttArgs {
     #[arg(long, env = "MQTT_PASSWORD")]
     mqtt_password: Option<String>,
 
+    #[command(flatten)]
+    tls: TlsArgs,
+
+    /// MQTT protocol version
+    #[arg(long, value_enum, default_value = "3", env = "MQTT_VERSION")]
+    mqtt_version: MqttVersion,
+
+    /// Seconds before an undelivered theme message expires on the broker (MQTT 5 only)
+    #[arg(long, env = "MQTT_MESSAGE_EXPIRY_SECS")]
+    mqtt_message_expiry_secs: Option<u32>,
+
     #[arg(long, default_value = "neiam/sync/theme", env = "MQTT_TOPIC")]
     mqtt_topic: String,
 
+    /// Publish the theme as a retained message so new subscribers receive it immediately
+    #[arg(long, env = "MQTT_RETAIN")]
+    mqtt_retain: bool,
+
+    /// Topic for a retained summary of the published theme, its phase and the next change
+    #[arg(long, env = "MQTT_STATE_TOPIC")]
+    mqtt_state_topic: Option<String>,
+
+    /// Topic that reads `online` while theme-sender is connected and `offline` otherwise
+    #[arg(long, env = "MQTT_AVAILABILITY_TOPIC")]
+    mqtt_availability_topic: Option<String>,
+
     #[arg(
         long,
         default_value = "neiam/sync/theme/override",
@@ -633,38 +982,52 @@ struct ThemeMqttArgs {
         env = "MQTT_REVERT_TOPIC"
     )]
     mqtt_revert_topic: String,
-}
 
-// Geolocation API integration
-#[derive(Debug, Deserialize)]
-struct IpApiResponse {
-    lat: f64,
-    lon: f64,
-}
+    #[arg(
+        long,
+        default_value = "neiam/sync/theme/relocate",
+        env = "MQTT_RELOCATE_TOPIC"
+    )]
+    mqtt_relocate_topic: String,
+
+    /// Topic (wildcards allowed) with OwnTracks or `{lat, lon}` JSON location updates
+    #[arg(long, env = "MQTT_LOCATION_TOPIC")]
+    mqtt_location_topic: Option<String>,
+
+    /// Topic (wildcards allowed) with illuminance readings from an ambient light sensor
+    #[arg(long, env = "MQTT_LUX_TOPIC")]
+    mqtt_lux_topic: Option<String>,
 
-#[derive(Debug, Clone, Copy)]
-struct Location {
-    latitude: f64,
-    longitude: f64,
+    /// Add the sun's elevation, azimuth, daylight level and color temperature to each payload
+    #[arg(long, env = "PUBLISH_SUN_POSITION")]
+    publish_sun_position: bool,
 }
 
-#[instrument]
-async fn get_location() -> Result<Location> {
-    // Use ip-api.com to get location based on IP
-    debug!("Fetching location from ip-api.com");
-    let response: IpApiResponse = reqwest::get("http://ip-api.com/json/?fields=lat,lon")
-        .await
-        .context("Failed to fetch geolocation")?
-        .json()
-        .await
-        .context("Failed to parse geolocation response")?;
-    debug!(
-        "Received location: lat={}, lon={}",
-        response.lat, response.lon
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_fields_round_the_position() {
+        let fields = SunFields::new(SunPosition {
+            elevation: 37.51234,
+            azimuth: 179.98765,
+        });
+
+        assert_eq!(fields.elevation, 37.51);
+        assert_eq!(fields.azimuth, 179.99);
+        assert_eq!(fields.daylight, 1.0);
+        assert_eq!(fields.color_temperature, 6278);
+    }
 
-    Ok(Location {
-        latitude: response.lat,
-        longitude: response.lon,
-    })
+    #[test]
+    fn sun_fields_at_the_horizon() {
+        let fields = SunFields::new(SunPosition {
+            elevation: 0.0,
+            azimuth: 90.0,
+        });
+
+        assert_eq!(fields.daylight, 0.5);
+        assert_eq!(fields.color_temperature, 2845);
+    }
 }
@@ -0,0 +1,371 @@
+use crate::ThemeMqttArgs;
+use anyhow::{Context, Result, bail};
+use paho_mqtt::PropertyCode;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration as StdDuration;
+use tracing::{debug, error, info};
+
+/// MQTT protocol version spoken to the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MqttVersion {
+    /// MQTT 3.1.1, falling back to 3.1
+    #[value(name = "3")]
+    V3,
+    /// MQTT 5, with message properties
+    #[value(name = "5")]
+    V5,
+}
+
+impl MqttVersion {
+    fn raw(self) -> u32 {
+        match self {
+            MqttVersion::V3 => paho_mqtt::MQTT_VERSION_DEFAULT,
+            MqttVersion::V5 => paho_mqtt::MQTT_VERSION_5,
+        }
+    }
+}
+
+/// Check whether a topic matches a subscription filter with `+` and `#` wildcards.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for filter_level in filter.split('/') {
+        match (filter_level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (expected, Some(level)) if expected == level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Payload published to the availability topic while connected.
+const AVAILABILITY_ONLINE: &str = "online";
+
+/// Payload the broker publishes to the availability topic once the connection is gone.
+const AVAILABILITY_OFFLINE: &str = "offline";
+
+/// Client options for the broker in `args`, speaking the configured protocol version.
+pub fn create_options(args: &ThemeMqttArgs, client_id: &str) -> paho_mqtt::CreateOptions {
+    paho_mqtt::CreateOptionsBuilder::new()
+        .server_uri(&args.mqtt_host)
+        .client_id(client_id)
+        .mqtt_version(args.mqtt_version.raw())
+        .finalize()
+}
+
+/// Connection options shared by the publisher and the listener, including authentication
+/// and TLS.
+pub fn connect_options(args: &ThemeMqttArgs) -> Result<paho_mqtt::ConnectOptionsBuilder> {
+    if args.mqtt_message_expiry_secs.is_some() && args.mqtt_version != MqttVersion::V5 {
+        bail!("Message expiry needs MQTT version 5");
+    }
+
+    let mut conn_opts_builder =
+        paho_mqtt::ConnectOptionsBuilder::with_mqtt_version(args.mqtt_version.raw());
+    conn_opts_builder.keep_alive_interval(StdDuration::from_secs(20));
+
+    if let (Some(username), Some(password)) = (&args.mqtt_username, &args.mqtt_password) {
+        debug!("Using MQTT authentication");
+        conn_opts_builder.user_name(username).password(password);
+    }
+
+    if let Some(ssl_opts) = args.tls.ssl_options(&args.mqtt_host)? {
+        debug!("Using TLS for MQTT");
+        conn_opts_builder.ssl_options(ssl_opts);
+    }
+
+    Ok(conn_opts_builder)
+}
+
+/// How long to wait for a single broker operation.
+const OPERATION_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+/// How often to check whether paho's automatic reconnect has brought the connection back.
+const RECONNECT_POLL_INTERVAL: StdDuration = StdDuration::from_millis(250);
+
+/// Wait for a paho token without blocking the runtime, giving up after `OPERATION_TIMEOUT`.
+async fn complete<T>(token: impl Future<Output = paho_mqtt::Result<T>>) -> Result<T> {
+    Ok(tokio::time::timeout(OPERATION_TIMEOUT, token)
+        .await
+        .context("Timed out waiting for the MQTT broker")??)
+}
+
+/// How long the broker keeps a persistent MQTT 5 session while the client is away.
+const SESSION_EXPIRY_SECS: u32 = 24 * 60 * 60;
+
+/// Ask the broker to keep the session, and with it the subscriptions, across reconnects.
+pub fn keep_session(
+    conn_opts_builder: &mut paho_mqtt::ConnectOptionsBuilder,
+    version: MqttVersion,
+) -> Result<()> {
+    match version {
+        MqttVersion::V3 => {
+            conn_opts_builder.clean_session(false);
+        }
+        MqttVersion::V5 => {
+            let mut props = paho_mqtt::Properties::new();
+            props.push_u32(PropertyCode::SessionExpiryInterval, SESSION_EXPIRY_SECS)?;
+            conn_opts_builder.clean_start(false).properties(props);
+        }
+    }
+    Ok(())
+}
+
+/// A long-lived MQTT connection used for every publish.
+///
+/// The connection is opened on first use and paho reconnects it in the background when
+/// the broker goes away; a publish that finds it down waits for that reconnect.
+///
+/// With an availability topic, the broker is told to publish a retained `offline` there
+/// if the connection drops, and every (re)connect publishes a retained `online`.
+pub struct Publisher {
+    client: paho_mqtt::AsyncClient,
+    conn_opts: paho_mqtt::ConnectOptions,
+    availability_topic: Option<String>,
+    /// Set after the first successful connect, from when paho's automatic reconnect runs
+    connected_once: AtomicBool,
+}
+
+impl Publisher {
+    pub fn new(args: &ThemeMqttArgs) -> Result<Self> {
+        let client = paho_mqtt::AsyncClient::new(create_options(args, "theme-sender"))
+            .context("Failed to create MQTT client")?;
+
+        let mut conn_opts_builder = connect_options(args)?;
+        conn_opts_builder
+            .automatic_reconnect(StdDuration::from_secs(1), StdDuration::from_secs(30));
+
+        if let Some(topic) = &args.mqtt_availability_topic {
+            conn_opts_builder.will_message(paho_mqtt::Message::new_retained(
+                topic,
+                AVAILABILITY_OFFLINE,
+                1,
+            ));
+
+            let online = paho_mqtt::Message::new_retained(topic, AVAILABILITY_ONLINE, 1);
+            client.set_connected_callback(move |client| {
+                debug!("Publishing availability to {}", online.topic());
+                client.publish(online.clone());
+            });
+        }
+
+        Ok(Self {
+            client,
+            conn_opts: conn_opts_builder.finalize(),
+            availability_topic: args.mqtt_availability_topic.clone(),
+            connected_once: AtomicBool::new(false),
+        })
+    }
+
+    async fn ensure_connected(&self) -> Result<()> {
+        if self.client.is_connected() {
+            return Ok(());
+        }
+
+        if self.connected_once.load(Ordering::Relaxed) {
+            // Connecting again would race paho's automatic reconnect
+            debug!("Waiting for MQTT publisher to reconnect");
+            return tokio::time::timeout(OPERATION_TIMEOUT, async {
+                while !self.client.is_connected() {
+                    tokio::time::sleep(RECONNECT_POLL_INTERVAL).await;
+                }
+            })
+            .await
+            .context("MQTT broker is still unreachable");
+        }
+
+        info!("Connecting MQTT publisher to broker");
+        complete(self.client.connect(self.conn_opts.clone()))
+            .await
+            .context("Failed to connect to MQTT broker")?;
+        self.connected_once.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Mark the service offline and close the connection cleanly.
+    pub async fn shutdown(&self) -> Result<()> {
+        if !self.client.is_connected() {
+            return Ok(());
+        }
+
+        if let Some(topic) = &self.availability_topic {
+            info!("📴 Publishing offline availability");
+            complete(self.client.publish(paho_mqtt::Message::new_retained(
+                topic.as_str(),
+                AVAILABILITY_OFFLINE,
+                1,
+            )))
+            .await
+            .context("Failed to publish offline availability")?;
+        }
+
+        complete(self.client.disconnect(None))
+            .await
+            .context("Failed to disconnect from MQTT broker")?;
+        Ok(())
+    }
+
+    /// Publish `messages`, retrying with exponential backoff.
+    pub async fn publish_with_retry(
+        &self,
+        messages: &[paho_mqtt::Message],
+        what: &str,
+    ) -> Result<()> {
+        // Retry logic with exponential backoff
+        let mut retry_delay = 1u64;
+        const MAX_RETRY_DELAY: u64 = 30;
+        const MAX_RETRIES: u32 = 5;
+
+        for attempt in 1..=MAX_RETRIES {
+            match self.try_publish(messages, attempt).await {
+                Ok(()) => {
+                    info!("✓ Sent {} successfully", what);
+                    return Ok(());
+                }
+                Err(e) if attempt < MAX_RETRIES => {
+                    error!(
+                        "Failed to send {} (attempt {}/{}): {}",
+                        what, attempt, MAX_RETRIES, e
+                    );
+                    debug!("Retrying in {} seconds...", retry_delay);
+                    tokio::time::sleep(StdDuration::from_secs(retry_delay)).await;
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to send {} after {} attempts: {}",
+                        what, MAX_RETRIES, e
+                    );
+                    return Err(e);
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!("Failed to send {} after all retries", what))
+    }
+
+    async fn try_publish(&self, messages: &[paho_mqtt::Message], attempt: u32) -> Result<()> {
+        debug!("Attempting to send MQTT message (attempt {})", attempt);
+        self.ensure_connected().await?;
+
+        for msg in messages {
+            debug!(
+                "Publishing to topic {} (retained: {}): {}",
+                msg.topic(),
+                msg.retained(),
+                msg.payload_str()
+            );
+            complete(self.client.publish(msg.clone()))
+                .await
+                .context("Failed to publish message")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A QoS 1 JSON message. Over MQTT 5 it carries its content type, `expiry_secs` and
+/// `user_properties`; MQTT 3 has no room for them.
+pub fn json_message(
+    args: &ThemeMqttArgs,
+    topic: &str,
+    payload: String,
+    retained: bool,
+    expiry_secs: Option<u32>,
+    user_properties: &[(&str, &str)],
+) -> Result<paho_mqtt::Message> {
+    let mut builder = paho_mqtt::MessageBuilder::new()
+        .topic(topic)
+        .payload(payload)
+        .qos(1)
+        .retained(retained);
+
+    if args.mqtt_version == MqttVersion::V5 {
+        let mut props = paho_mqtt::Properties::new();
+        props.push_string(PropertyCode::ContentType, "application/json")?;
+        if let Some(expiry_secs) = expiry_secs {
+            props.push_u32(PropertyCode::MessageExpiryInterval, expiry_secs)?;
+        }
+        for (key, value) in user_properties {
+            props.push_string_pair(PropertyCode::UserProperty, key, value)?;
+        }
+        builder = builder.properties(props);
+    }
+
+    Ok(builder.finalize())
+}
+
+/// Whether an incoming message's content type, if it declares one, is text the listener
+/// can parse: `text/*` or JSON. Messages without one (all of MQTT 3) are accepted.
+pub fn is_text_content_type(content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return true;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/") || essence == "application/json" || essence.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_matches_filters() {
+        let cases = [
+            ("owntracks/+/+", "owntracks/alice/phone", true),
+            ("owntracks/+/+", "owntracks/alice", false),
+            ("owntracks/+/+", "owntracks/alice/phone/event", false),
+            ("owntracks/#", "owntracks", true),
+            ("owntracks/#", "owntracks/alice/phone", true),
+            ("#", "any/topic", true),
+            ("+", "single", true),
+            ("+", "two/levels", false),
+            ("home/+/lux", "home/office/lux", true),
+            ("home/+/lux", "home/office/temperature", false),
+            ("home/office/lux", "home/office/lux", true),
+            ("home/office/lux", "home/office", false),
+            ("home/office", "home/office/lux", false),
+            ("home/+", "home/", true),
+        ];
+
+        for (filter, topic, expected) in cases {
+            assert_eq!(
+                topic_matches(filter, topic),
+                expected,
+                "{} against {}",
+                filter,
+                topic
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_text_and_json_content_types() {
+        let cases = [
+            (None, true),
+            (Some("text/plain"), true),
+            (Some("text/plain; charset=utf-8"), true),
+            (Some("application/json"), true),
+            (Some("Application/JSON"), true),
+            (Some("application/vnd.owntracks+json"), true),
+            (Some("application/octet-stream"), false),
+            (Some("image/png"), false),
+            (Some(""), false),
+        ];
+        for (content_type, accepted) in cases {
+            assert_eq!(
+                is_text_content_type(content_type),
+                accepted,
+                "{:?}",
+                content_type
+            );
+        }
+    }
+}
@@ -1,6 +1,9 @@
+mod tls;
+
 use anyhow::{Context, Result};
 use clap::Parser;
 use std::time::Duration;
+use tls::TlsArgs;
 use tracing::{debug, info, instrument};
 
 #[derive(Debug, Parser)]
@@ -30,6 +33,9 @@ struct MqttArgs {
     #[arg(long, env = "MQTT_PASSWORD")]
     mqtt_password: Option<String>,
 
+    #[command(flatten)]
+    tls: TlsArgs,
+
     #[arg(
         long,
         default_value = "neiam/sync/theme/override",
@@ -77,6 +83,11 @@ fn main() -> Result<()> {
         conn_opts_builder.user_name(username).password(password);
     }
 
+    if let Some(ssl_opts) = args.mqtt.tls.ssl_options(&args.mqtt.mqtt_host)? {
+        debug!("Using TLS");
+        conn_opts_builder.ssl_options(ssl_opts);
+    }
+
     let conn_opts = conn_opts_builder.finalize();
 
     info!("Connecting to MQTT broker at {}...", args.mqtt.mqtt_host);
@@ -0,0 +1,289 @@
+use crate::theme::{ThemeEngine, Transition};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Parser, Clone)]
+pub struct ScheduleArgs {
+    /// Number of days of upcoming transitions to print
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..=366))]
+    pub days: u32,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: ScheduleFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScheduleFormat {
+    Table,
+    Json,
+    Ics,
+}
+
+/// A theme phase in the printed schedule.
+#[derive(Debug, Serialize)]
+struct Entry {
+    phase: String,
+    theme: String,
+    description: &'static str,
+    utc: DateTime<Utc>,
+    local: DateTime<Local>,
+    /// When the phase gives way to the next one, if that falls within the computed days.
+    until: Option<DateTime<Utc>>,
+}
+
+/// Print every transition from `start` through the following `args.days` days to stdout.
+pub fn print(engine: &ThemeEngine, start: DateTime<Utc>, args: &ScheduleArgs) -> Result<()> {
+    let end = start + Duration::days(args.days as i64);
+    let entries = upcoming(engine, start, end);
+
+    let mut out = std::io::stdout().lock();
+    match args.format {
+        ScheduleFormat::Table => write_table(&mut out, &entries)?,
+        ScheduleFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            writeln!(out)?;
+        }
+        ScheduleFormat::Ics => write_ics(&mut out, &entries, start)?,
+    }
+    Ok(())
+}
+
+/// Transitions in `[start, end)`, each paired with the time the following phase begins.
+fn upcoming(engine: &ThemeEngine, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Entry> {
+    // One extra day on each side so phases straddling the range still get an end time
+    let first = engine.local_date(start - Duration::days(1));
+    let last = engine.local_date(end + Duration::days(1));
+
+    let mut transitions: Vec<Transition> = first
+        .iter_days()
+        .take_while(|date| *date <= last)
+        .flat_map(|date| engine.transitions(date))
+        .collect();
+    transitions.sort_by_key(|transition| transition.time);
+
+    transitions
+        .iter()
+        .enumerate()
+        .filter(|(_, transition)| transition.time >= start && transition.time < end)
+        .map(|(index, transition)| Entry {
+            phase: transition.theme.phase_name(),
+            theme: transition.theme.to_theme_string(),
+            description: transition.theme.to_description(),
+            utc: transition.time,
+            local: transition.time.with_timezone(&Local),
+            until: transitions.get(index + 1).map(|next| next.time),
+        })
+        .collect()
+}
+
+fn write_table(out: &mut impl Write, entries: &[Entry]) -> Result<()> {
+    writeln!(out, "{:<20} {:<26} {:<17} THEME", "UTC", "LOCAL", "PHASE")?;
+    for entry in entries {
+        writeln!(
+            out,
+            "{:<20} {:<26} {:<17} {}",
+            entry.utc.format("%Y-%m-%d %H:%M:%S"),
+            entry.local.format("%Y-%m-%d %H:%M:%S %:z"),
+            entry.phase,
+            entry.theme
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the schedule as an iCalendar feed with one event per phase.
+fn write_ics(out: &mut impl Write, entries: &[Entry], generated: DateTime<Utc>) -> Result<()> {
+    const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+    write_ics_line(out, "BEGIN:VCALENDAR")?;
+    write_ics_line(out, "VERSION:2.0")?;
+    write_ics_line(out, "PRODID:-//neiam//theme-sender//EN")?;
+    write_ics_line(out, "X-WR-CALNAME:Theme schedule")?;
+    for entry in entries {
+        write_ics_line(out, "BEGIN:VEVENT")?;
+        write_ics_line(
+            out,
+            &format!(
+                "UID:{}-{}@theme-sender",
+                entry.utc.timestamp(),
+                entry.phase.to_lowercase()
+            ),
+        )?;
+        write_ics_line(out, &format!("DTSTAMP:{}", generated.format(FORMAT)))?;
+        write_ics_line(out, &format!("DTSTART:{}", entry.utc.format(FORMAT)))?;
+        write_ics_line(
+            out,
+            &format!("DTEND:{}", entry.until.unwrap_or(entry.utc).format(FORMAT)),
+        )?;
+        write_ics_line(
+            out,
+            &format!(
+                "SUMMARY:{}",
+                escape_ics(&format!("{} ({})", entry.phase, entry.theme))
+            ),
+        )?;
+        write_ics_line(
+            out,
+            &format!("DESCRIPTION:{}", escape_ics(entry.description)),
+        )?;
+        write_ics_line(out, "TRANSP:TRANSPARENT")?;
+        write_ics_line(out, "END:VEVENT")?;
+    }
+    write_ics_line(out, "END:VCALENDAR")?;
+    Ok(())
+}
+
+/// Longest iCalendar content line in octets, excluding the CRLF (RFC 5545 section 3.1).
+const ICS_LINE_OCTETS: usize = 75;
+
+/// Write one content line ending in CRLF, folding it onto continuation lines that start
+/// with a space when it is too long. Folds never split a UTF-8 character.
+fn write_ics_line(out: &mut impl Write, line: &str) -> Result<()> {
+    let mut rest = line;
+    let mut limit = ICS_LINE_OCTETS;
+    while rest.len() > limit {
+        let mut split = limit;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        write!(out, "{}\r\n ", &rest[..split])?;
+        rest = &rest[split..];
+        // The leading space of a continuation line counts towards its length
+        limit = ICS_LINE_OCTETS - 1;
+    }
+    write!(out, "{}\r\n", rest)?;
+    Ok(())
+}
+
+/// Escape an iCalendar TEXT value.
+fn escape_ics(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunrise::Coordinates;
+
+    fn utc(instant: &str) -> DateTime<Utc> {
+        instant.parse().unwrap()
+    }
+
+    fn berlin() -> ThemeEngine {
+        ThemeEngine::new(Coordinates::new(52.52, 13.405).unwrap(), 0.0)
+    }
+
+    fn entry(phase: &str, theme: &str, utc: DateTime<Utc>, until: Option<DateTime<Utc>>) -> Entry {
+        Entry {
+            phase: phase.to_string(),
+            theme: theme.to_string(),
+            description: "Full daylight",
+            utc,
+            local: utc.with_timezone(&Local),
+            until,
+        }
+    }
+
+    #[test]
+    fn upcoming_keeps_transitions_in_range() {
+        let start = utc("2026-03-20T12:00:00Z");
+        let end = utc("2026-03-21T12:00:00Z");
+        let entries = upcoming(&berlin(), start, end);
+
+        assert_eq!(entries.len(), 9);
+        assert!(entries.iter().all(|e| e.utc >= start && e.utc < end));
+        assert_eq!(entries[0].phase, "CivilDusk");
+        assert_eq!(entries.last().unwrap().phase, "Day");
+
+        // A range starting exactly at a transition includes it, and one ending there does not
+        let dusk = entries[0].utc;
+        assert_eq!(upcoming(&berlin(), dusk, end)[0].utc, dusk);
+        assert_eq!(upcoming(&berlin(), start, dusk).len(), 0);
+    }
+
+    #[test]
+    fn upcoming_chains_until_across_days() {
+        let entries = upcoming(
+            &berlin(),
+            utc("2026-03-20T12:00:00Z"),
+            utc("2026-03-22T12:00:00Z"),
+        );
+
+        for pair in entries.windows(2) {
+            assert_eq!(pair[0].until, Some(pair[1].utc), "{}", pair[0].phase);
+        }
+        // Night runs past midnight into the next local day's dawn
+        let night = entries.iter().find(|e| e.phase == "Night").unwrap();
+        assert!(night.until.unwrap().date_naive() > night.utc.date_naive());
+
+        // The last phase ends at a transition beyond the range
+        let last = entries.last().unwrap();
+        assert_eq!(last.phase, "Day");
+        assert!(last.until.unwrap() > utc("2026-03-22T12:00:00Z"));
+    }
+
+    #[test]
+    fn ics_uses_crlf_escapes_text_and_ends_the_last_event_at_its_start() {
+        let entries = [
+            entry(
+                "Day",
+                "light",
+                utc("2026-03-21T05:52:09Z"),
+                Some(utc("2026-03-21T17:20:04Z")),
+            ),
+            entry(
+                "warm; soft, low",
+                "light\\warm",
+                utc("2026-03-21T17:20:04Z"),
+                None,
+            ),
+        ];
+        let mut out = Vec::new();
+        write_ics(&mut out, &entries, utc("2026-03-21T00:00:00Z")).unwrap();
+        let ics = String::from_utf8(out).unwrap();
+
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(!ics.replace("\r\n", "").contains('\n'));
+        let lines: Vec<_> = ics.split_terminator("\r\n").collect();
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 2);
+
+        assert!(lines.contains(&"DTSTART:20260321T055209Z"));
+        assert!(lines.contains(&"DTEND:20260321T172004Z"));
+        assert!(lines.contains(&"SUMMARY:warm\\; soft\\, low (light\\\\warm)"));
+        // Without a following transition the last event has no length
+        let last_event = &lines[lines.iter().rposition(|l| *l == "BEGIN:VEVENT").unwrap()..];
+        assert!(last_event.contains(&"DTSTART:20260321T172004Z"));
+        assert!(last_event.contains(&"DTEND:20260321T172004Z"));
+    }
+
+    #[test]
+    fn escapes_ics_text() {
+        assert_eq!(escape_ics("a\\b;c,d\ne"), "a\\\\b\\;c\\,d\\ne");
+        assert_eq!(escape_ics("plain text"), "plain text");
+    }
+
+    #[test]
+    fn folds_long_ics_lines() {
+        let summary = format!("SUMMARY:{}", "très long ".repeat(20));
+        let mut out = Vec::new();
+        write_ics_line(&mut out, &summary).unwrap();
+        let folded = String::from_utf8(out).unwrap();
+
+        let lines: Vec<_> = folded.split_terminator("\r\n").collect();
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|line| line.len() <= ICS_LINE_OCTETS));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", summary));
+
+        let mut out = Vec::new();
+        write_ics_line(&mut out, "VERSION:2.0").unwrap();
+        assert_eq!(out, b"VERSION:2.0\r\n");
+    }
+}
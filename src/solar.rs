@@ -0,0 +1,220 @@
+use chrono::{DateTime, Duration, NaiveDate, Timelike, Utc};
+use sunrise::Coordinates;
+
+/// Sun elevation in degrees at and below which there is no daylight at all.
+const DARK_ELEVATION_DEG: f64 = -6.0;
+
+/// Sun elevation in degrees from which it is full daylight.
+const BRIGHT_ELEVATION_DEG: f64 = 6.0;
+
+/// Color temperature in Kelvin of warm light, used while the sun is low or down.
+const WARM_COLOR_TEMPERATURE_K: f64 = 2700.0;
+
+/// Color temperature in Kelvin of cool daylight with the sun high in the sky.
+const COOL_COLOR_TEMPERATURE_K: f64 = 6500.0;
+
+/// Sun elevation in degrees at which the color temperature reaches its coolest.
+const COOL_ELEVATION_DEG: f64 = 45.0;
+
+/// Where the sun is in the sky as seen by an observer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    /// Degrees above the horizon
+    pub elevation: f64,
+    /// Degrees clockwise from true north
+    pub azimuth: f64,
+}
+
+/// The sun's position using the NOAA solar position equations.
+pub fn sun_position(coordinates: Coordinates, instant: DateTime<Utc>) -> SunPosition {
+    let parameters = SolarParameters::at(instant);
+
+    let minutes = instant.num_seconds_from_midnight() as f64 / 60.0;
+    let true_solar_time =
+        (minutes + parameters.equation_of_time + 4.0 * coordinates.lon()).rem_euclid(1440.0);
+    let hour_angle = (true_solar_time / 4.0 - 180.0).to_radians();
+
+    let latitude = coordinates.lat().to_radians();
+    let declination = parameters.declination;
+    let cos_zenith =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let zenith = cos_zenith.clamp(-1.0, 1.0).acos();
+
+    // Azimuth is undefined with the sun straight overhead or an observer at a pole
+    let denominator = latitude.cos() * zenith.sin();
+    let azimuth = if denominator.abs() < 1e-9 {
+        180.0
+    } else {
+        let angle = ((latitude.sin() * zenith.cos() - declination.sin()) / denominator)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees();
+        if hour_angle > 0.0 {
+            (angle + 180.0).rem_euclid(360.0)
+        } else {
+            (540.0 - angle).rem_euclid(360.0)
+        }
+    };
+
+    SunPosition {
+        elevation: 90.0 - zenith.to_degrees(),
+        azimuth,
+    }
+}
+
+/// Solar elevation above the horizon in degrees, using the NOAA solar position equations.
+pub fn solar_elevation(coordinates: Coordinates, instant: DateTime<Utc>) -> f64 {
+    sun_position(coordinates, instant).elevation
+}
+
+/// How much daylight the sun provides at `elevation`, from 0.0 (dark) to 1.0 (full day).
+pub fn daylight(elevation: f64) -> f64 {
+    smoothstep(DARK_ELEVATION_DEG, BRIGHT_ELEVATION_DEG, elevation)
+}
+
+/// Suggested light color temperature in Kelvin, warm with the sun low and cool with it high.
+pub fn color_temperature(elevation: f64) -> f64 {
+    let coolness = smoothstep(DARK_ELEVATION_DEG, COOL_ELEVATION_DEG, elevation);
+    WARM_COLOR_TEMPERATURE_K + (COOL_COLOR_TEMPERATURE_K - WARM_COLOR_TEMPERATURE_K) * coolness
+}
+
+/// Hermite interpolation from 0.0 at `edge0` to 1.0 at `edge1`.
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// When the sun crosses the meridian on `date`, the moment it is highest in the sky.
+pub fn solar_noon(coordinates: Coordinates, date: NaiveDate) -> DateTime<Utc> {
+    let utc_noon = date.and_hms_opt(12, 0, 0).unwrap_or_default().and_utc();
+    let approximate = utc_noon - Duration::seconds((coordinates.lon() * 240.0).round() as i64);
+
+    // The equation of time barely changes within a day, so evaluating it near noon is enough
+    let equation_of_time = SolarParameters::at(approximate).equation_of_time;
+    approximate - Duration::seconds((equation_of_time * 60.0).round() as i64)
+}
+
+/// The sun's declination and the equation of time at an instant.
+struct SolarParameters {
+    /// Declination in radians
+    declination: f64,
+    /// Difference between true and mean solar time in minutes
+    equation_of_time: f64,
+}
+
+impl SolarParameters {
+    fn at(instant: DateTime<Utc>) -> Self {
+        let julian_day = instant.timestamp() as f64 / 86400.0 + 2440587.5;
+        let t = (julian_day - 2451545.0) / 36525.0;
+
+        // Geometric mean longitude and anomaly of the sun, and Earth's orbital eccentricity
+        let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+        let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+        let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+
+        let m = mean_anomaly.to_radians();
+        let center = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+            + (2.0 * m).sin() * (0.019993 - 0.000101 * t)
+            + (3.0 * m).sin() * 0.000289;
+
+        let omega = (125.04 - 1934.136 * t).to_radians();
+        let apparent_longitude =
+            (mean_longitude + center - 0.00569 - 0.00478 * omega.sin()).to_radians();
+
+        let mean_obliquity =
+            23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+        let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+        let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+        // Equation of time in minutes
+        let y = (obliquity / 2.0).tan().powi(2);
+        let l0 = mean_longitude.to_radians();
+        let equation_of_time = 4.0
+            * (y * (2.0 * l0).sin() - 2.0 * eccentricity * m.sin()
+                + 4.0 * eccentricity * y * m.sin() * (2.0 * l0).cos()
+                - 0.5 * y * y * (4.0 * l0).sin()
+                - 1.25 * eccentricity * eccentricity * (2.0 * m).sin())
+            .to_degrees();
+
+        Self {
+            declination,
+            equation_of_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin() -> Coordinates {
+        Coordinates::new(52.52, 13.405).unwrap()
+    }
+
+    fn equinox() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, 21).unwrap()
+    }
+
+    #[test]
+    fn sun_is_due_south_and_highest_at_solar_noon() {
+        let noon = solar_noon(berlin(), equinox());
+        let position = sun_position(berlin(), noon);
+
+        assert!((position.elevation - 37.5).abs() < 0.5, "{:?}", position);
+        assert!((position.azimuth - 180.0).abs() < 0.5, "{:?}", position);
+        for minutes in [-30, 30] {
+            let elevation = solar_elevation(berlin(), noon + Duration::minutes(minutes));
+            assert!(elevation < position.elevation, "{} minutes", minutes);
+        }
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        let noon = solar_noon(berlin(), equinox());
+        let morning = sun_position(berlin(), noon - Duration::hours(4));
+        let evening = sun_position(berlin(), noon + Duration::hours(4));
+
+        assert!(
+            (90.0..180.0).contains(&morning.azimuth),
+            "morning {:?}",
+            morning
+        );
+        assert!(
+            (180.0..270.0).contains(&evening.azimuth),
+            "evening {:?}",
+            evening
+        );
+        assert!((morning.elevation - evening.elevation).abs() < 1.0);
+    }
+
+    #[test]
+    fn daylight_ramps_between_dark_and_bright_elevations() {
+        assert_eq!(daylight(-90.0), 0.0);
+        assert_eq!(daylight(DARK_ELEVATION_DEG), 0.0);
+        assert_eq!(daylight(0.0), 0.5);
+        assert_eq!(daylight(BRIGHT_ELEVATION_DEG), 1.0);
+        assert_eq!(daylight(COOL_ELEVATION_DEG), 1.0);
+    }
+
+    #[test]
+    fn color_temperature_ramps_from_warm_to_cool() {
+        assert_eq!(color_temperature(-90.0), WARM_COLOR_TEMPERATURE_K);
+        assert_eq!(
+            color_temperature(DARK_ELEVATION_DEG),
+            WARM_COLOR_TEMPERATURE_K
+        );
+
+        let low_sun = color_temperature(BRIGHT_ELEVATION_DEG);
+        assert!(
+            low_sun > WARM_COLOR_TEMPERATURE_K && low_sun < 3500.0,
+            "{}",
+            low_sun
+        );
+
+        assert_eq!(
+            color_temperature(COOL_ELEVATION_DEG),
+            COOL_COLOR_TEMPERATURE_K
+        );
+        assert_eq!(color_temperature(90.0), COOL_COLOR_TEMPERATURE_K);
+    }
+}
@@ -0,0 +1,946 @@
+use crate::solar;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use sunrise::{Coordinates, DawnType, SolarDay, SolarEvent};
+
+/// Solar elevation in degrees of the upper rim touching the horizon, as used by sunrise.
+const SUNRISE_ELEVATION_DEG: f64 = -0.833;
+
+/// Solar elevation in degrees at which the Sunrise phase gives way to full Day.
+const DAY_ELEVATION_DEG: f64 = 6.0;
+
+/// Solar elevation in degrees where blue hour gives way to the rest of civil twilight.
+const BLUE_HOUR_END_DEG: f64 = -4.0;
+
+/// How long the SolarNoon phase lasts on either side of the sun's highest point.
+const SOLAR_NOON_HALF_WINDOW_MINS: i64 = 30;
+
+/// Spacing of the elevation samples used to find band boundary crossings.
+const CROSSING_SCAN_STEP_MINS: i64 = 10;
+
+/// Band crossings use the NOAA elevation while the standard phases come from sunrise, and
+/// the two disagree by up to a minute or so. Breakpoints this close together are treated
+/// as one, so matching thresholds don't produce momentary phases.
+const BREAKPOINT_MERGE_SECS: i64 = 120;
+
+/// How far back to look for the phase in effect; polar day and night last under a year.
+const PHASE_LOOKBACK_DAYS: usize = 366;
+
+/// Standard phases shorter than this next to an elevation band are absorbed into the band,
+/// so a band edge close to a solar event does not flash the standard theme.
+const BAND_GAP_MERGE_MINS: i64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ThemeType {
+    Night,
+    AstronomicalDawn,
+    NauticalDawn,
+    CivilDawn,
+    Sunrise,
+    Day,
+    CivilDusk,
+    NauticalDusk,
+    AstronomicalDusk,
+    GoldenHour,
+    BlueHour,
+    SolarNoon,
+    /// A configured solar elevation band and the theme string it publishes.
+    Band {
+        name: String,
+        theme: String,
+    },
+    /// A solar phase whose published theme was adjusted for the room's ambient light.
+    Ambient {
+        phase: Box<ThemeType>,
+        theme: String,
+    },
+    Custom(String),
+}
+
+impl ThemeType {
+    /// Parse a solar phase from its kebab-case name, e.g. `civil-dusk`.
+    pub fn from_phase_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "night" => Some(ThemeType::Night),
+            "astronomical-dawn" => Some(ThemeType::AstronomicalDawn),
+            "nautical-dawn" => Some(ThemeType::NauticalDawn),
+            "civil-dawn" => Some(ThemeType::CivilDawn),
+            "sunrise" => Some(ThemeType::Sunrise),
+            "day" => Some(ThemeType::Day),
+            "civil-dusk" => Some(ThemeType::CivilDusk),
+            "nautical-dusk" => Some(ThemeType::NauticalDusk),
+            "astronomical-dusk" => Some(ThemeType::AstronomicalDusk),
+            "golden-hour" => Some(ThemeType::GoldenHour),
+            "blue-hour" => Some(ThemeType::BlueHour),
+            "solar-noon" => Some(ThemeType::SolarNoon),
+            _ => None,
+        }
+    }
+
+    pub fn to_theme_string(&self) -> String {
+        match self {
+            ThemeType::Night => "dark".to_string(),
+            ThemeType::AstronomicalDawn => "dark-dimmed".to_string(),
+            ThemeType::NauticalDawn => "dark-soft".to_string(),
+            ThemeType::CivilDawn => "light-soft".to_string(),
+            ThemeType::Sunrise => "light".to_string(),
+            ThemeType::Day => "light".to_string(),
+            ThemeType::CivilDusk => "light-soft".to_string(),
+            ThemeType::NauticalDusk => "dark-soft".to_string(),
+            ThemeType::AstronomicalDusk => "dark-dimmed".to_string(),
+            ThemeType::GoldenHour => "light-warm".to_string(),
+            ThemeType::BlueHour => "dark-blue".to_string(),
+            ThemeType::SolarNoon => "light-bright".to_string(),
+            ThemeType::Band { theme, .. } => theme.clone(),
+            ThemeType::Ambient { theme, .. } => theme.clone(),
+            ThemeType::Custom(theme) => theme.clone(),
+        }
+    }
+
+    pub fn to_description(&self) -> &'static str {
+        match self {
+            ThemeType::Night => "Full night - stars visible",
+            ThemeType::AstronomicalDawn => "Astronomical dawn - faint light appears in sky",
+            ThemeType::NauticalDawn => "Nautical dawn - horizon becomes visible",
+            ThemeType::CivilDawn => "Civil dawn - enough light for outdoor activities",
+            ThemeType::Sunrise => "Sunrise - sun breaks the horizon",
+            ThemeType::Day => "Full daylight",
+            ThemeType::CivilDusk => "Civil dusk - sun below horizon, still light out",
+            ThemeType::NauticalDusk => "Nautical dusk - darker, horizon still visible",
+            ThemeType::AstronomicalDusk => "Astronomical dusk - fading light in sky",
+            ThemeType::GoldenHour => "Golden hour - low sun, warm soft light",
+            ThemeType::BlueHour => "Blue hour - sun just below horizon, deep blue sky",
+            ThemeType::SolarNoon => "Solar noon - sun at its highest",
+            ThemeType::Band { .. } => "Configured solar elevation band",
+            ThemeType::Ambient { phase, .. } => phase.to_description(),
+            ThemeType::Custom(_theme) => "Custom theme override",
+        }
+    }
+
+    /// Short name of the phase for schedules and explanations.
+    pub fn phase_name(&self) -> String {
+        match self {
+            ThemeType::Band { name, .. } => name.clone(),
+            ThemeType::Ambient { phase, .. } => phase.phase_name(),
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+/// Optional phases that refine the standard ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtraPhase {
+    /// Replaces Sunrise in the morning and ends Day from 6° in the evening until sunset
+    GoldenHour,
+    /// The -6° to -4° slice of civil twilight, at dawn and at dusk
+    BlueHour,
+    /// The hour around the sun's highest point, while it is above the horizon
+    SolarNoon,
+}
+
+/// Which half of the day an elevation band applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Rising,
+    Setting,
+}
+
+/// A custom phase active while the sun is between `min` and `max` degrees of elevation.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElevationBand {
+    pub name: String,
+    /// Theme string to publish, defaulting to the band's name
+    #[serde(default)]
+    pub theme: Option<String>,
+    pub min: f64,
+    pub max: f64,
+    /// Only match while the sun is rising or setting; both when unset
+    #[serde(default)]
+    pub direction: Option<Direction>,
+}
+
+impl ElevationBand {
+    pub fn contains(&self, sun_elevation: f64, rising: bool) -> bool {
+        let direction_matches = match self.direction {
+            Some(Direction::Rising) => rising,
+            Some(Direction::Setting) => !rising,
+            None => true,
+        };
+        direction_matches && sun_elevation >= self.min && sun_elevation < self.max
+    }
+
+    pub fn theme_type(&self) -> ThemeType {
+        ThemeType::Band {
+            name: self.name.clone(),
+            theme: self.theme.clone().unwrap_or_else(|| self.name.clone()),
+        }
+    }
+}
+
+/// The moment a solar theme phase begins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub theme: ThemeType,
+    pub time: DateTime<Utc>,
+}
+
+/// The solar theme active at an instant and when it next changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeState {
+    pub theme: ThemeType,
+    /// When the active phase began, or `None` if it has lasted for days (polar day or night).
+    pub since: Option<DateTime<Utc>>,
+    pub next: Option<Transition>,
+}
+
+/// Computes solar theme phases for a fixed observer.
+#[derive(Debug, Clone)]
+pub struct ThemeEngine {
+    coordinates: Coordinates,
+    elevation: f64,
+    offsets: HashMap<ThemeType, Duration>,
+    bands: Vec<ElevationBand>,
+    extra_phases: Vec<ExtraPhase>,
+}
+
+impl ThemeEngine {
+    /// `elevation` is the observer's height above sea level in meters.
+    pub fn new(coordinates: Coordinates, elevation: f64) -> Self {
+        Self {
+            coordinates,
+            elevation,
+            offsets: HashMap::new(),
+            bands: Vec::new(),
+            extra_phases: Vec::new(),
+        }
+    }
+
+    /// Shift the start of each listed phase away from its solar event.
+    pub fn with_offsets(mut self, offsets: HashMap<ThemeType, Duration>) -> Self {
+        self.offsets = offsets;
+        self
+    }
+
+    /// Elevation bands that take precedence over the standard phases, first match wins.
+    pub fn with_bands(mut self, bands: Vec<ElevationBand>) -> Self {
+        self.bands = bands;
+        self
+    }
+
+    /// Enable optional phases on top of the standard ones.
+    pub fn with_extra_phases(mut self, extra_phases: Vec<ExtraPhase>) -> Self {
+        self.extra_phases = extra_phases;
+        self
+    }
+
+    /// How far the start of `theme` is shifted from its solar event.
+    pub fn offset(&self, theme: &ThemeType) -> Duration {
+        self.offsets.get(theme).copied().unwrap_or_default()
+    }
+
+    /// Local solar date containing `instant`.
+    pub fn local_date(&self, instant: DateTime<Utc>) -> NaiveDate {
+        (instant + self.solar_time_offset()).date_naive()
+    }
+
+    /// Offset of local mean solar time from UTC, four minutes per degree of longitude.
+    fn solar_time_offset(&self) -> Duration {
+        Duration::seconds((self.coordinates.lon() * 240.0).round() as i64)
+    }
+
+    /// Whether the sun is climbing at `instant`.
+    pub fn sun_rising(&self, instant: DateTime<Utc>) -> bool {
+        self.sun_slope(instant) > 0.0
+    }
+
+    /// The first configured band the sun is in at `instant`.
+    pub fn matching_band(&self, instant: DateTime<Utc>) -> Option<&ElevationBand> {
+        let elevation = self.sun_elevation(instant);
+        let rising = self.sun_rising(instant);
+        self.bands
+            .iter()
+            .find(|band| band.contains(elevation, rising))
+    }
+
+    /// Theme transitions for one local solar day.
+    ///
+    /// Without elevation bands these are the standard phase transitions, minus any that
+    /// would repeat the phase already in effect, however many days ago it began (the 6°
+    /// rising event during midnight sun).
+    /// With bands, the theme is re-evaluated wherever the sun crosses a band boundary or
+    /// changes direction, and a transition is recorded wherever the result changes.
+    pub fn transitions(&self, date: NaiveDate) -> Vec<Transition> {
+        let mut phases = self.phase_transitions(date);
+        if self.bands.is_empty() {
+            let mut previous = self.phase_before(date);
+            phases.retain(|transition| {
+                if previous.as_ref() == Some(&transition.theme) {
+                    return false;
+                }
+                previous = Some(transition.theme.clone());
+                true
+            });
+            return phases;
+        }
+
+        let standard = self.phase_transitions_around(date);
+        let Some(midnight) = date.and_hms_opt(0, 0, 0) else {
+            return phases;
+        };
+        let start = midnight.and_utc() - self.solar_time_offset();
+        let end = start + Duration::days(1);
+
+        let mut breakpoints: Vec<_> = phases.iter().map(|transition| transition.time).collect();
+        for band in &self.bands {
+            for threshold in [band.min, band.max] {
+                breakpoints.extend(find_crossings(start, end, |instant| {
+                    self.sun_elevation(instant) - threshold
+                }));
+            }
+        }
+        if self.bands.iter().any(|band| band.direction.is_some()) {
+            breakpoints.extend(find_crossings(start, end, |instant| {
+                self.sun_slope(instant)
+            }));
+        }
+        breakpoints.sort();
+        breakpoints.dedup();
+
+        let Some(&first) = breakpoints.first() else {
+            return Vec::new();
+        };
+        let mut previous = self.classify(first - Duration::seconds(1), &standard);
+
+        // Nearby breakpoints are merged and take the theme that holds after the last of them
+        let merge_window = Duration::seconds(BREAKPOINT_MERGE_SECS);
+        let mut transitions: Vec<_> = breakpoints
+            .chunk_by(|a, b| *b - *a <= merge_window)
+            .filter_map(|cluster| {
+                let theme = self.classify(*cluster.last()?, &standard);
+                if theme == previous {
+                    return None;
+                }
+                previous = theme.clone();
+                Some(Transition {
+                    theme,
+                    time: cluster[0],
+                })
+            })
+            .collect();
+        absorb_band_gaps(&mut transitions);
+        transitions
+    }
+
+    /// The standard phase in effect at the start of `date`: that of the last transition on
+    /// an earlier day, walking back over days without any (polar day or night).
+    fn phase_before(&self, date: NaiveDate) -> Option<ThemeType> {
+        std::iter::successors(date.pred_opt(), |day| day.pred_opt())
+            .take(PHASE_LOOKBACK_DAYS)
+            .find_map(|day| self.phase_transitions(day).pop())
+            .map(|transition| transition.theme)
+    }
+
+    /// Standard phase transitions for the local dates around `date`.
+    fn phase_transitions_around(&self, date: NaiveDate) -> Vec<Transition> {
+        let mut transitions: Vec<_> = [date.pred_opt(), Some(date), date.succ_opt()]
+            .into_iter()
+            .flatten()
+            .flat_map(|date| self.phase_transitions(date))
+            .collect();
+
+        transitions.sort_by_key(|transition| transition.time);
+        transitions
+    }
+
+    /// The matching elevation band at `instant`, or else the standard phase from `standard`.
+    fn classify(&self, instant: DateTime<Utc>, standard: &[Transition]) -> ThemeType {
+        if let Some(band) = self.matching_band(instant) {
+            return band.theme_type();
+        }
+
+        let split = standard.partition_point(|transition| transition.time <= instant);
+        match split.checked_sub(1) {
+            Some(index) => standard[index].theme.clone(),
+            None => steady_theme(self.sun_elevation(instant)),
+        }
+    }
+
+    /// Change in elevation over the two minutes around `instant`.
+    fn sun_slope(&self, instant: DateTime<Utc>) -> f64 {
+        let minute = Duration::minutes(1);
+        self.sun_elevation(instant + minute) - self.sun_elevation(instant - minute)
+    }
+
+    /// Standard phase transitions for one local solar day; each phase starts at its event
+    /// plus any configured offset and lasts until the next one, with Night running from
+    /// astronomical dusk to astronomical dawn.
+    fn phase_transitions(&self, date: NaiveDate) -> Vec<Transition> {
+        let solar_day = SolarDay::new(self.coordinates, date).with_altitude(self.elevation);
+        let golden_hour = self.extra_phases.contains(&ExtraPhase::GoldenHour);
+        let blue_hour = self.extra_phases.contains(&ExtraPhase::BlueHour);
+
+        let mut events = vec![
+            (
+                ThemeType::AstronomicalDawn,
+                SolarEvent::Dawn(DawnType::Astronomical),
+            ),
+            (
+                ThemeType::NauticalDawn,
+                SolarEvent::Dawn(DawnType::Nautical),
+            ),
+        ];
+        if blue_hour {
+            events.push((ThemeType::BlueHour, SolarEvent::Dawn(DawnType::Civil)));
+            events.push((
+                ThemeType::CivilDawn,
+                elevation_event(BLUE_HOUR_END_DEG, true),
+            ));
+        } else {
+            events.push((ThemeType::CivilDawn, SolarEvent::Dawn(DawnType::Civil)));
+        }
+        if golden_hour {
+            events.push((ThemeType::GoldenHour, SolarEvent::Sunrise));
+        } else {
+            events.push((ThemeType::Sunrise, SolarEvent::Sunrise));
+        }
+        events.push((ThemeType::Day, elevation_event(DAY_ELEVATION_DEG, true)));
+        if golden_hour {
+            events.push((
+                ThemeType::GoldenHour,
+                elevation_event(DAY_ELEVATION_DEG, false),
+            ));
+        }
+        events.push((ThemeType::CivilDusk, SolarEvent::Sunset));
+        if blue_hour {
+            events.push((
+                ThemeType::BlueHour,
+                elevation_event(BLUE_HOUR_END_DEG, false),
+            ));
+        }
+        events.extend([
+            (ThemeType::NauticalDusk, SolarEvent::Dusk(DawnType::Civil)),
+            (
+                ThemeType::AstronomicalDusk,
+                SolarEvent::Dusk(DawnType::Nautical),
+            ),
+            (ThemeType::Night, SolarEvent::Dusk(DawnType::Astronomical)),
+        ]);
+
+        let mut transitions: Vec<_> = events
+            .into_iter()
+            .filter_map(|(theme, event)| {
+                let time = event_time(&solar_day, date, event)? + self.offset(&theme);
+                Some(Transition { theme, time })
+            })
+            .collect();
+        transitions.sort_by_key(|transition| transition.time);
+
+        if self.extra_phases.contains(&ExtraPhase::SolarNoon) {
+            self.insert_solar_noon(date, &mut transitions);
+        }
+        transitions
+    }
+
+    /// Interrupt whatever phase spans solar noon with the SolarNoon phase, then resume it.
+    fn insert_solar_noon(&self, date: NaiveDate, transitions: &mut Vec<Transition>) {
+        let noon = solar::solar_noon(self.coordinates, date) + self.offset(&ThemeType::SolarNoon);
+        if self.sun_elevation(noon) < SUNRISE_ELEVATION_DEG {
+            return;
+        }
+
+        let half_window = Duration::minutes(SOLAR_NOON_HALF_WINDOW_MINS);
+        let (start, end) = (noon - half_window, noon + half_window);
+        let resume = transitions
+            .iter()
+            .rfind(|transition| transition.time <= end)
+            .map(|transition| transition.theme.clone())
+            .unwrap_or_else(|| steady_theme(self.sun_elevation(end)));
+
+        transitions.retain(|transition| transition.time < start || transition.time > end);
+        transitions.push(Transition {
+            theme: ThemeType::SolarNoon,
+            time: start,
+        });
+        transitions.push(Transition {
+            theme: resume,
+            time: end,
+        });
+        transitions.sort_by_key(|transition| transition.time);
+    }
+
+    /// The sun's elevation above the horizon in degrees at `instant`.
+    pub fn sun_elevation(&self, instant: DateTime<Utc>) -> f64 {
+        solar::solar_elevation(self.coordinates, instant)
+    }
+
+    /// The sun's elevation and azimuth at `instant`.
+    pub fn sun_position(&self, instant: DateTime<Utc>) -> solar::SunPosition {
+        solar::sun_position(self.coordinates, instant)
+    }
+
+    /// Transitions for yesterday, today and tomorrow in local solar time around `instant`.
+    pub fn transitions_around(&self, instant: DateTime<Utc>) -> Vec<Transition> {
+        let today = self.local_date(instant);
+
+        let mut transitions: Vec<_> = [today.pred_opt(), Some(today), today.succ_opt()]
+            .into_iter()
+            .flatten()
+            .flat_map(|date| self.transitions(date))
+            .collect();
+
+        transitions.sort_by_key(|transition| transition.time);
+        transitions
+    }
+
+    /// The phase of the last transition at or before `instant`, and the one after it.
+    ///
+    /// Without any recent transition (deep polar day or night) the theme follows the sun's
+    /// current elevation instead.
+    pub fn theme_at(&self, instant: DateTime<Utc>) -> ThemeState {
+        let today = self.local_date(instant);
+        let transitions = self.transitions_around(instant);
+        let split = transitions.partition_point(|transition| transition.time <= instant);
+        let next = transitions.get(split).cloned();
+
+        match split.checked_sub(1).map(|index| &transitions[index]) {
+            Some(current) => ThemeState {
+                theme: current.theme.clone(),
+                since: Some(current.time),
+                next,
+            },
+            None => ThemeState {
+                theme: self.classify(instant, &self.phase_transitions_around(today)),
+                since: None,
+                next,
+            },
+        }
+    }
+}
+
+/// The moment the sun crosses `degrees` above the horizon.
+///
+/// `SolarEvent::Elevation` measures the angle below the horizon, so it is negated here.
+fn elevation_event(degrees: f64, morning: bool) -> SolarEvent {
+    SolarEvent::Elevation {
+        elevation: (-degrees).to_radians(),
+        morning,
+    }
+}
+
+/// When `event` happens on `date`, or `None` if the sun never reaches its elevation that
+/// day (polar day or night).
+fn event_time(solar_day: &SolarDay, date: NaiveDate, event: SolarEvent) -> Option<DateTime<Utc>> {
+    let time = solar_day.event_time(event);
+
+    // sunrise reports events that never happen as the Unix epoch, far outside the day
+    let noon = date.and_hms_opt(12, 0, 0)?.and_utc();
+    ((time - noon).abs() <= Duration::days(1)).then_some(time)
+}
+
+/// Times in `[start, end)` where `f` changes sign, to within a second.
+fn find_crossings(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    f: impl Fn(DateTime<Utc>) -> f64,
+) -> Vec<DateTime<Utc>> {
+    let step = Duration::minutes(CROSSING_SCAN_STEP_MINS);
+    let mut crossings = Vec::new();
+
+    let mut low = start;
+    let mut low_positive = f(low) >= 0.0;
+    while low < end {
+        let high = (low + step).min(end);
+        let high_positive = f(high) >= 0.0;
+
+        if high_positive != low_positive {
+            // Bisect down to the first second on the new side of zero
+            let (mut a, mut b) = (low, high);
+            while b - a > Duration::seconds(1) {
+                let mid = a + (b - a) / 2;
+                if (f(mid) >= 0.0) == low_positive {
+                    a = mid;
+                } else {
+                    b = mid;
+                }
+            }
+            crossings.push(b);
+        }
+
+        low = high;
+        low_positive = high_positive;
+    }
+
+    crossings
+}
+
+/// Fold short standard phases into a neighbouring band: the band before the gap runs on
+/// through it, or else the band after it starts early.
+fn absorb_band_gaps(transitions: &mut Vec<Transition>) {
+    let is_band = |transition: &Transition| matches!(transition.theme, ThemeType::Band { .. });
+    let min_gap = Duration::minutes(BAND_GAP_MERGE_MINS);
+
+    let mut index = 0;
+    while index + 1 < transitions.len() {
+        let gap = &transitions[index];
+        let next = &transitions[index + 1];
+        if is_band(gap) || next.time - gap.time >= min_gap {
+            index += 1;
+            continue;
+        }
+
+        let follows_band = index > 0 && is_band(&transitions[index - 1]);
+        if !follows_band && !is_band(next) {
+            index += 1;
+            continue;
+        }
+        if !follows_band {
+            transitions[index + 1].time = transitions[index].time;
+        }
+        transitions.remove(index);
+        index = index.saturating_sub(1);
+    }
+
+    transitions.dedup_by(|later, earlier| later.theme == earlier.theme);
+}
+
+/// Theme for a sun that has stayed at roughly the same elevation for days.
+fn steady_theme(sun_elevation: f64) -> ThemeType {
+    match sun_elevation {
+        e if e >= SUNRISE_ELEVATION_DEG => ThemeType::Day,
+        e if e >= -6.0 => ThemeType::CivilDusk,
+        e if e >= -12.0 => ThemeType::NauticalDusk,
+        e if e >= -18.0 => ThemeType::AstronomicalDusk,
+        _ => ThemeType::Night,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(latitude: f64, longitude: f64) -> ThemeEngine {
+        ThemeEngine::new(Coordinates::new(latitude, longitude).unwrap(), 0.0)
+    }
+
+    fn utc(instant: &str) -> DateTime<Utc> {
+        instant.parse().unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    /// Transitions for `days` consecutive local days, merged the way `schedule` does.
+    fn schedule(engine: &ThemeEngine, start: &str, days: u64) -> Vec<Transition> {
+        let mut transitions: Vec<_> = date(start)
+            .iter_days()
+            .take(days as usize)
+            .flat_map(|date| engine.transitions(date))
+            .collect();
+        transitions.sort_by_key(|transition| transition.time);
+        transitions
+    }
+
+    fn berlin() -> ThemeEngine {
+        engine(52.52, 13.405)
+    }
+
+    /// Event times are rounded to whole seconds by the solar calculation; allow for that.
+    fn assert_near(actual: DateTime<Utc>, expected: &str) {
+        let difference = (actual - utc(expected)).num_seconds().abs();
+        assert!(difference <= 2, "expected {}, got {}", expected, actual);
+    }
+
+    /// Check `theme_at` against the phase, its start, and the next transition.
+    fn assert_state(
+        engine: &ThemeEngine,
+        at: &str,
+        theme: ThemeType,
+        since: &str,
+        next: (ThemeType, &str),
+    ) {
+        let state = engine.theme_at(utc(at));
+        assert_eq!(state.theme, theme, "theme at {}", at);
+        assert_near(state.since.expect("since"), since);
+        let transition = state.next.expect("next transition");
+        assert_eq!(transition.theme, next.0, "next theme after {}", at);
+        assert_near(transition.time, next.1);
+    }
+
+    fn themes(transitions: &[Transition]) -> Vec<ThemeType> {
+        transitions.iter().map(|t| t.theme.clone()).collect()
+    }
+
+    #[test]
+    fn berlin_transitions_follow_the_standard_phases() {
+        let transitions = berlin().transitions(date("2026-03-21"));
+
+        assert_eq!(
+            themes(&transitions),
+            [
+                ThemeType::AstronomicalDawn,
+                ThemeType::NauticalDawn,
+                ThemeType::CivilDawn,
+                ThemeType::Sunrise,
+                ThemeType::Day,
+                ThemeType::CivilDusk,
+                ThemeType::NauticalDusk,
+                ThemeType::AstronomicalDusk,
+                ThemeType::Night,
+            ]
+        );
+        assert_near(transitions[0].time, "2026-03-21T03:10:22Z");
+        assert_near(transitions[4].time, "2026-03-21T05:52:09Z");
+        assert_near(transitions[8].time, "2026-03-21T19:16:50Z");
+    }
+
+    #[test]
+    fn berlin_theme_at_covers_every_standard_phase() {
+        use ThemeType::*;
+        let berlin = berlin();
+
+        #[rustfmt::skip]
+        let cases = [
+            ("2026-03-20T18:00:00Z", NauticalDusk, "2026-03-20T17:52:23Z", (AstronomicalDusk, "2026-03-20T18:32:41Z")),
+            ("2026-03-20T19:00:00Z", AstronomicalDusk, "2026-03-20T18:32:41Z", (Night, "2026-03-20T19:14:45Z")),
+            ("2026-03-21T00:00:00Z", Night, "2026-03-20T19:14:45Z", (AstronomicalDawn, "2026-03-21T03:10:22Z")),
+            ("2026-03-21T03:30:00Z", AstronomicalDawn, "2026-03-21T03:10:22Z", (NauticalDawn, "2026-03-21T03:52:37Z")),
+            ("2026-03-21T04:00:00Z", NauticalDawn, "2026-03-21T03:52:37Z", (CivilDawn, "2026-03-21T04:33:01Z")),
+            ("2026-03-21T04:45:00Z", CivilDawn, "2026-03-21T04:33:01Z", (Sunrise, "2026-03-21T05:07:08Z")),
+            ("2026-03-21T05:30:00Z", Sunrise, "2026-03-21T05:07:08Z", (Day, "2026-03-21T05:52:09Z")),
+            ("2026-03-21T12:00:00Z", Day, "2026-03-21T05:52:09Z", (CivilDusk, "2026-03-21T17:20:04Z")),
+            ("2026-03-21T17:30:00Z", CivilDusk, "2026-03-21T17:20:04Z", (NauticalDusk, "2026-03-21T17:54:10Z")),
+        ];
+        for (at, theme, since, next) in cases {
+            assert_state(&berlin, at, theme, since, next);
+        }
+    }
+
+    #[test]
+    fn berlin_theme_at_covers_every_extra_phase() {
+        use ThemeType::*;
+        let berlin = berlin().with_extra_phases(vec![
+            ExtraPhase::GoldenHour,
+            ExtraPhase::BlueHour,
+            ExtraPhase::SolarNoon,
+        ]);
+
+        #[rustfmt::skip]
+        let cases = [
+            ("2026-03-20T17:00:00Z", GoldenHour, "2026-03-20T16:33:15Z", (CivilDusk, "2026-03-20T17:18:19Z")),
+            ("2026-03-20T17:45:00Z", BlueHour, "2026-03-20T17:39:10Z", (NauticalDusk, "2026-03-20T17:52:23Z")),
+            ("2026-03-21T04:40:00Z", BlueHour, "2026-03-21T04:33:01Z", (CivilDawn, "2026-03-21T04:46:16Z")),
+            ("2026-03-21T05:30:00Z", GoldenHour, "2026-03-21T05:07:08Z", (Day, "2026-03-21T05:52:09Z")),
+            ("2026-03-21T09:00:00Z", Day, "2026-03-21T05:52:09Z", (SolarNoon, "2026-03-21T10:43:32Z")),
+            ("2026-03-21T11:00:00Z", SolarNoon, "2026-03-21T10:43:32Z", (Day, "2026-03-21T11:43:32Z")),
+        ];
+        for (at, theme, since, next) in cases {
+            assert_state(&berlin, at, theme, since, next);
+        }
+    }
+
+    #[test]
+    fn sydney_solar_day_starts_the_previous_utc_evening() {
+        let sydney = engine(-33.87, 151.21);
+        assert_eq!(
+            sydney.local_date(utc("2026-06-20T21:00:00Z")),
+            date("2026-06-21")
+        );
+
+        let transitions = sydney.transitions(date("2026-06-21"));
+        assert_eq!(transitions[0].theme, ThemeType::AstronomicalDawn);
+        assert_eq!(transitions[3].theme, ThemeType::Sunrise);
+        assert_near(transitions[3].time, "2026-06-20T20:59:54Z");
+        assert_eq!(transitions.last().unwrap().theme, ThemeType::Night);
+        assert_near(transitions.last().unwrap().time, "2026-06-21T08:23:08Z");
+
+        assert_state(
+            &sydney,
+            "2026-06-21T02:00:00Z",
+            ThemeType::Day,
+            "2026-06-20T21:38:13Z",
+            (ThemeType::CivilDusk, "2026-06-21T06:53:41Z"),
+        );
+        assert_state(
+            &sydney,
+            "2026-06-21T12:00:00Z",
+            ThemeType::Night,
+            "2026-06-21T08:23:08Z",
+            (ThemeType::AstronomicalDawn, "2026-06-21T19:30:40Z"),
+        );
+    }
+
+    #[test]
+    fn date_line_sides_share_the_sun_but_not_the_date() {
+        let east = engine(-17.0, 179.0);
+        let west = engine(-17.0, -179.0);
+        let instant = utc("2026-01-10T00:00:00Z");
+
+        assert_eq!(east.local_date(instant), date("2026-01-10"));
+        assert_eq!(west.local_date(instant), date("2026-01-09"));
+
+        // The same dawn is a day apart in local dates and eight minutes apart in time
+        let east_dawn = &east.transitions(date("2026-01-11"))[0];
+        let west_dawn = &west.transitions(date("2026-01-10"))[0];
+        assert_eq!(east_dawn.theme, ThemeType::AstronomicalDawn);
+        assert_eq!(west_dawn.theme, ThemeType::AstronomicalDawn);
+        assert_near(east_dawn.time, "2026-01-10T16:19:01Z");
+        assert_near(west_dawn.time, "2026-01-10T16:11:00Z");
+
+        for (engine, dusk) in [
+            (&east, "2026-01-10T06:43:16Z"),
+            (&west, "2026-01-10T06:35:16Z"),
+        ] {
+            let state = engine.theme_at(instant);
+            assert_eq!(state.theme, ThemeType::Day);
+            assert!(state.since.unwrap() > instant - Duration::hours(12));
+            let next = state.next.unwrap();
+            assert_eq!(next.theme, ThemeType::CivilDusk);
+            assert_near(next.time, dusk);
+        }
+    }
+
+    #[test]
+    fn readme_bands_do_not_flash_standard_phases() {
+        let bands: Vec<ElevationBand> = serde_json::from_str(
+            r#"[
+                { "name": "golden", "theme": "light-warm", "min": 0, "max": 6 },
+                { "name": "blue-hour", "theme": "dark-blue", "min": -6, "max": -4, "direction": "setting" }
+            ]"#,
+        )
+        .unwrap();
+        let berlin = berlin().with_bands(bands.clone());
+        let golden = bands[0].theme_type();
+        let blue_hour = bands[1].theme_type();
+
+        let transitions = berlin.transitions(date("2026-03-20"));
+        assert_eq!(
+            themes(&transitions),
+            [
+                ThemeType::AstronomicalDawn,
+                ThemeType::NauticalDawn,
+                ThemeType::CivilDawn,
+                golden.clone(),
+                ThemeType::Day,
+                golden.clone(),
+                ThemeType::CivilDusk,
+                blue_hour.clone(),
+                ThemeType::NauticalDusk,
+                ThemeType::AstronomicalDusk,
+                ThemeType::Night,
+            ]
+        );
+        for pair in transitions.windows(2) {
+            assert!(
+                pair[1].time - pair[0].time >= Duration::minutes(BAND_GAP_MERGE_MINS),
+                "{:?} lasts only until {}",
+                pair[0],
+                pair[1].time
+            );
+        }
+
+        // Golden hour starts at sunrise and ends at sunset rather than at 0°
+        assert_near(transitions[3].time, "2026-03-20T05:09:30Z");
+        assert_near(transitions[6].time, "2026-03-20T17:18:19Z");
+        assert_state(
+            &berlin,
+            "2026-03-20T17:15:00Z",
+            golden,
+            "2026-03-20T16:34:22Z",
+            (ThemeType::CivilDusk, "2026-03-20T17:18:19Z"),
+        );
+        assert_state(
+            &berlin,
+            "2026-03-20T17:45:00Z",
+            blue_hour,
+            "2026-03-20T17:40:21Z",
+            (ThemeType::NauticalDusk, "2026-03-20T17:52:23Z"),
+        );
+    }
+
+    fn assert_no_repeats(transitions: &[Transition]) {
+        for pair in transitions.windows(2) {
+            assert_ne!(
+                pair[0].theme, pair[1].theme,
+                "repeated transition at {} and {}",
+                pair[0].time, pair[1].time
+            );
+        }
+    }
+
+    #[test]
+    fn tromso_midnight_sun_has_no_repeated_day() {
+        let tromso = engine(69.65, 18.96);
+
+        // The last sunset is on May 17; from then on only the daily 6° rising event
+        // happens, and it must not restart Day
+        let transitions = schedule(&tromso, "2026-05-17", 40);
+        assert_eq!(
+            themes(&transitions),
+            [
+                ThemeType::Sunrise,
+                ThemeType::Day,
+                ThemeType::CivilDusk,
+                ThemeType::Day,
+            ]
+        );
+        assert_near(transitions[0].time, "2026-05-16T23:15:31Z");
+        assert_near(transitions[1].time, "2026-05-17T02:05:44Z");
+        assert_near(transitions[2].time, "2026-05-17T22:05:48Z");
+        assert_near(transitions[3].time, "2026-05-18T02:02:25Z");
+
+        let state = tromso.theme_at(utc("2026-06-21T00:30:00Z"));
+        assert_eq!(state.theme, ThemeType::Day);
+        assert_eq!(state.since, None);
+        assert_eq!(state.next, None);
+    }
+
+    #[test]
+    fn tromso_polar_night_never_reaches_day() {
+        let tromso = engine(69.65, 18.96);
+
+        let transitions = schedule(&tromso, "2026-12-15", 10);
+        assert_no_repeats(&transitions);
+        assert!(!transitions.is_empty());
+        assert!(
+            transitions
+                .iter()
+                .all(|t| !matches!(t.theme, ThemeType::Sunrise | ThemeType::Day))
+        );
+    }
+
+    #[test]
+    fn svalbard_spring_and_summer_have_no_repeats() {
+        let svalbard = engine(78.22, 15.65);
+
+        let spring = schedule(&svalbard, "2026-04-05", 14);
+        assert_no_repeats(&spring);
+        assert!(spring.iter().any(|t| t.theme == ThemeType::Day));
+
+        // Day begins on April 19 and lasts through the midnight sun; the 6° rising event
+        // on August 2, after months without any transition, must not restart it
+        let season = schedule(&svalbard, "2026-04-01", 180);
+        assert_no_repeats(&season);
+        let start = season
+            .iter()
+            .position(|t| t.time > utc("2026-04-19T00:00:00Z"))
+            .unwrap();
+        assert_eq!(season[start].theme, ThemeType::Day);
+        assert_near(season[start].time, "2026-04-19T03:16:34Z");
+        assert_ne!(season[start + 1].theme, ThemeType::Day);
+        assert!(season[start + 1].time > utc("2026-08-14T00:00:00Z"));
+
+        assert_eq!(schedule(&svalbard, "2026-07-15", 30), []);
+
+        for instant in ["2026-06-21T00:00:00Z", "2026-08-03T00:00:00Z"] {
+            let state = svalbard.theme_at(utc(instant));
+            assert_eq!(state.theme, ThemeType::Day, "{}", instant);
+            assert_eq!(state.since, None, "{}", instant);
+            assert_eq!(state.next, None, "{}", instant);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+#[derive(Debug, Parser, Clone)]
+pub struct TlsArgs {
+    /// PEM file with the CA certificates that sign the broker's certificate; the system
+    /// trust store is used when unset
+    #[arg(long, env = "MQTT_CA_FILE")]
+    pub mqtt_ca_file: Option<PathBuf>,
+
+    /// PEM client certificate for mutual TLS, optionally followed by its private key
+    #[arg(long, env = "MQTT_CLIENT_CERT")]
+    pub mqtt_client_cert: Option<PathBuf>,
+
+    /// PEM private key for the client certificate
+    #[arg(long, env = "MQTT_CLIENT_KEY", requires = "mqtt_client_cert")]
+    pub mqtt_client_key: Option<PathBuf>,
+
+    /// Skip verification of the broker's certificate and hostname
+    #[arg(long, env = "MQTT_TLS_INSECURE")]
+    pub mqtt_tls_insecure: bool,
+
+    /// Comma-separated ALPN protocols to offer, e.g. `mqtt` or `x-amzn-mqtt-ca`
+    #[arg(long, env = "MQTT_ALPN", value_delimiter = ',')]
+    pub mqtt_alpn: Vec<String>,
+}
+
+impl TlsArgs {
+    fn is_configured(&self) -> bool {
+        self.mqtt_ca_file.is_some()
+            || self.mqtt_client_cert.is_some()
+            || self.mqtt_tls_insecure
+            || !self.mqtt_alpn.is_empty()
+    }
+
+    /// TLS settings for a connection to `host`, or `None` for a plain TCP broker.
+    pub fn ssl_options(&self, host: &str) -> Result<Option<paho_mqtt::SslOptions>> {
+        if !is_tls_uri(host) {
+            if self.is_configured() {
+                bail!(
+                    "TLS options need an ssl:// or mqtts:// MQTT host, got {}",
+                    host
+                );
+            }
+            return Ok(None);
+        }
+
+        let mut builder = paho_mqtt::SslOptionsBuilder::new();
+
+        if let Some(ca_file) = &self.mqtt_ca_file {
+            debug!("Using MQTT CA file {}", ca_file.display());
+            builder.trust_store(readable(ca_file, "CA file")?)?;
+        }
+
+        if let Some(cert) = &self.mqtt_client_cert {
+            debug!("Using MQTT client certificate {}", cert.display());
+            builder.key_store(readable(cert, "client certificate")?)?;
+        }
+
+        if let Some(key) = &self.mqtt_client_key {
+            builder.private_key(readable(key, "client key")?)?;
+        }
+
+        if self.mqtt_tls_insecure {
+            warn!("MQTT broker certificate verification is disabled");
+            builder.enable_server_cert_auth(false).verify(false);
+        }
+
+        if !self.mqtt_alpn.is_empty() {
+            let protos: Vec<&str> = self.mqtt_alpn.iter().map(String::as_str).collect();
+            builder.alpn_protos(&protos);
+        }
+
+        Ok(Some(builder.finalize()))
+    }
+}
+
+/// Fail early on a missing file, since paho only reports a generic TLS connect failure.
+fn readable<'a>(path: &'a Path, what: &str) -> Result<&'a Path> {
+    File::open(path).with_context(|| format!("Cannot read {} {}", what, path.display()))?;
+    Ok(path)
+}
+
+/// Whether `host` is a broker URI that paho connects to over TLS.
+fn is_tls_uri(host: &str) -> bool {
+    ["ssl://", "mqtts://", "wss://"]
+        .iter()
+        .any(|scheme| host.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_tls() -> TlsArgs {
+        TlsArgs {
+            mqtt_ca_file: None,
+            mqtt_client_cert: None,
+            mqtt_client_key: None,
+            mqtt_tls_insecure: false,
+            mqtt_alpn: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn recognizes_tls_schemes() {
+        for host in ["ssl://broker:8883", "mqtts://broker", "wss://broker/mqtt"] {
+            assert!(is_tls_uri(host), "{}", host);
+        }
+        for host in [
+            "tcp://broker:1883",
+            "mqtt://broker",
+            "ws://broker",
+            "broker:8883",
+        ] {
+            assert!(!is_tls_uri(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn plain_host_without_tls_options_uses_tcp() {
+        assert!(no_tls().ssl_options("tcp://broker:1883").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_tls_options_on_plain_host() {
+        let configured = [
+            TlsArgs {
+                mqtt_ca_file: Some(PathBuf::from("ca.crt")),
+                ..no_tls()
+            },
+            TlsArgs {
+                mqtt_client_cert: Some(PathBuf::from("client.pem")),
+                ..no_tls()
+            },
+            TlsArgs {
+                mqtt_tls_insecure: true,
+                ..no_tls()
+            },
+            TlsArgs {
+                mqtt_alpn: vec!["mqtt".to_string()],
+                ..no_tls()
+            },
+        ];
+        for args in configured {
+            let error = args.ssl_options("tcp://broker:1883").unwrap_err();
+            assert!(
+                error.to_string().contains("need an ssl:// or mqtts://"),
+                "{:?}: {}",
+                args,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn reports_missing_ca_file() {
+        let args = TlsArgs {
+            mqtt_ca_file: Some(PathBuf::from("/nonexistent/ca.crt")),
+            ..no_tls()
+        };
+        let error = args.ssl_options("ssl://broker:8883").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Cannot read CA file /nonexistent/ca.crt"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn reports_missing_client_key() {
+        let args = TlsArgs {
+            mqtt_client_cert: Some(PathBuf::from(file!())),
+            mqtt_client_key: Some(PathBuf::from("/nonexistent/client.key")),
+            ..no_tls()
+        };
+        let error = args.ssl_options("mqtts://broker").unwrap_err();
+        assert!(
+            error.to_string().contains("Cannot read client key"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn tls_host_gets_ssl_options() {
+        assert!(no_tls().ssl_options("ssl://broker:8883").unwrap().is_some());
+
+        let args = TlsArgs {
+            mqtt_ca_file: Some(PathBuf::from(file!())),
+            mqtt_alpn: vec!["mqtt".to_string()],
+            ..no_tls()
+        };
+        assert!(args.ssl_options("mqtts://broker").unwrap().is_some());
+    }
+}
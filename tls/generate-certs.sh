@@ -0,0 +1,32 @@
+#!/usr/bin/env bash
+# Generate a throwaway CA plus broker and client certificates for testing MQTT over TLS
+# with the mqtt-broker-tls compose service. Do not use these certificates in production.
+set -euo pipefail
+
+cd "$(dirname "$0")"
+mkdir -p certs
+cd certs
+
+DAYS=${DAYS:-365}
+SERVER_NAMES=${SERVER_NAMES:-DNS:localhost,DNS:mqtt-broker-tls,IP:127.0.0.1}
+
+openssl req -x509 -new -newkey rsa:2048 -nodes -days "$DAYS" \
+  -keyout ca.key -out ca.crt -subj "/CN=theme-sender test CA"
+
+openssl req -new -newkey rsa:2048 -nodes \
+  -keyout server.key -out server.csr -subj "/CN=mqtt-broker-tls"
+printf 'subjectAltName=%s\nextendedKeyUsage=serverAuth\n' "$SERVER_NAMES" > server.ext
+openssl x509 -req -in server.csr -CA ca.crt -CAkey ca.key -CAcreateserial \
+  -days "$DAYS" -extfile server.ext -out server.crt
+
+openssl req -new -newkey rsa:2048 -nodes \
+  -keyout client.key -out client.csr -subj "/CN=theme-sender"
+printf 'extendedKeyUsage=clientAuth\n' > client.ext
+openssl x509 -req -in client.csr -CA ca.crt -CAkey ca.key -CAcreateserial \
+  -days "$DAYS" -extfile client.ext -out client.crt
+
+rm -f server.csr client.csr server.ext client.ext ca.srl
+# The mosquitto container runs as its own user and must be able to read the server key
+chmod 644 server.key
+
+echo "Certificates written to $(pwd)"